@@ -0,0 +1,264 @@
+//! # cli
+//!
+//! Command-line argument definitions for the `permafrost` binary.
+//!
+//! Running `permafrost` with no arguments falls back to the interactive prompt,
+//! while subcommands allow the compiler to be driven from scripts and CI.
+
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use permafrost::compile::graph::export::GraphFormat;
+use permafrost::osts_reader::minified_path;
+use permafrost::{CompileOptions, ParserBackend};
+
+/// Process exit codes returned by the `permafrost` binary.
+///
+/// Invalid command-line usage is reported with [`exit_code::USAGE`], the code `clap` uses.
+pub mod exit_code {
+    /// Compilation finished and the output was delivered
+    pub const SUCCESS: u8 = 0;
+    /// The script could not be compiled
+    pub const COMPILE_ERROR: u8 = 1;
+    /// The command line was invalid or incomplete
+    pub const USAGE: u8 = 2;
+    /// The input could not be read or the output could not be written
+    pub const IO_ERROR: u8 = 3;
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "permafrost", version, about = "❄️ Condense frosts scripts down to the methods they use")]
+pub struct Cli {
+    /// Subcommand to run. Starts the interactive prompt when omitted.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Compile a `.osts` script down to the frosts methods it uses
+    Compile(CompileArgs),
+    /// Show why each kept symbol, or just `symbol`, is included in the compiled script
+    Explain(ExplainArgs),
+    /// Print the call graph of the frosts namespace, highlighting what the script uses
+    Graph(GraphArgs),
+    /// Report cycles, dead helpers, fan-in/fan-out and method costs of a frosts library
+    #[command(name = "analyze-lib")]
+    AnalyzeLib(AnalyzeLibArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CompileArgs {
+    /// Path to the `.osts` script to compile
+    pub path: PathBuf,
+
+    /// Write the compiled code to this file
+    #[arg(short, long, value_name = "FILE", conflicts_with = "stdout")]
+    pub output: Option<PathBuf>,
+
+    /// Print the compiled code to stdout instead of copying it to the clipboard
+    #[arg(long)]
+    pub stdout: bool,
+
+    /// Emit a complete `.osts` script instead of bare TypeScript.
+    /// Written to `<name>.min.osts` next to the source unless `-o` or `--stdout` is given,
+    /// and a source that is already `.min.osts` needs one of them.
+    #[arg(long)]
+    pub osts: bool,
+
+    /// Leave your code outside the `fr` namespace exactly as written, comments included
+    #[arg(long)]
+    pub preserve_main: bool,
+
+    /// Keep `/*! ... */` license-style comments inside the `fr` namespace
+    #[arg(long)]
+    pub keep_license_comments: bool,
+
+    /// Skip re-parsing the compiled script to check it defines everything it uses
+    #[arg(long)]
+    pub no_verify: bool,
+
+    #[command(flatten)]
+    pub parser: ParserArgs,
+}
+
+/// The analyzer selection shared by every subcommand.
+#[derive(Args, Debug)]
+pub struct ParserArgs {
+    /// Analyzer used to find the methods your code calls.
+    /// `ast` needs a build with the `ast` feature and otherwise falls back to `legacy`.
+    #[arg(long = "parser", value_enum, default_value_t = ParserBackend::Legacy)]
+    pub backend: ParserBackend,
+}
+
+#[derive(Args, Debug)]
+pub struct ExplainArgs {
+    /// Path to the `.osts` script to explain
+    pub path: PathBuf,
+
+    /// Only explain this symbol, like `merge`, `fr.read_csv` or `fr::DataFrame::merge`
+    pub symbol: Option<String>,
+
+    #[command(flatten)]
+    pub parser: ParserArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct GraphArgs {
+    /// Path to the `.osts` script whose namespace to draw
+    pub path: PathBuf,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+    pub format: GraphFormat,
+
+    #[command(flatten)]
+    pub parser: ParserArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct AnalyzeLibArgs {
+    /// Path to the library, like `frosts.ts`, or a `.osts` script that embeds it
+    pub path: PathBuf,
+
+    #[command(flatten)]
+    pub parser: ParserArgs,
+}
+
+impl ParserArgs {
+    /// Builds compile options that analyze with the selected parser. Verification is off,
+    /// so `explain`, `graph` and `analyze-lib` still work on scripts that wouldn't verify.
+    pub fn options(&self) -> CompileOptions {
+        let mut options = CompileOptions::default();
+        options.parser = self.backend;
+        options.verify = false;
+        options
+    }
+}
+
+impl CompileArgs {
+    /// Builds the library compile options selected by these arguments.
+    pub fn options(&self) -> CompileOptions {
+        let mut options = self.parser.options();
+        options.preserve_main = self.preserve_main;
+        options.keep_license_comments = self.keep_license_comments;
+        options.verify = !self.no_verify;
+        options
+    }
+
+    /// Where `--osts` writes the script: `-o` if given, otherwise `<name>.min.osts` next
+    /// to the source. Returns `None` when that would overwrite the source, as it would
+    /// for a script that is already `.min.osts`.
+    pub fn osts_output(&self) -> Option<PathBuf> {
+        if let Some(output) = &self.output {
+            return Some(output.clone());
+        }
+        let output = minified_path(&self.path);
+        (output != self.path).then_some(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_arguments_selects_interactive_mode() {
+        let cli = Cli::try_parse_from(["permafrost"]).unwrap();
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn parses_compile_with_output() {
+        let cli = Cli::try_parse_from(["permafrost", "compile", "intro.osts", "-o", "out.ts"]).unwrap();
+        let Some(Command::Compile(args)) = cli.command else { panic!("expected compile") };
+        assert_eq!(args.path, PathBuf::from("intro.osts"));
+        assert_eq!(args.output, Some(PathBuf::from("out.ts")));
+        assert!(!args.stdout);
+    }
+
+    #[test]
+    fn output_and_stdout_conflict() {
+        let parsed = Cli::try_parse_from(["permafrost", "compile", "a.osts", "-o", "b.ts", "--stdout"]);
+        assert!(parsed.is_err());
+    }
+
+    #[test]
+    fn formatting_flags_map_to_options() {
+        let cli = Cli::try_parse_from(["permafrost", "compile", "a.osts", "--preserve-main", "--keep-license-comments"]).unwrap();
+        let Some(Command::Compile(args)) = cli.command else { panic!("expected compile") };
+        let options = args.options();
+        assert!(options.preserve_main);
+        assert!(options.keep_license_comments);
+        assert!(options.verify);
+    }
+
+    #[test]
+    fn no_verify_turns_verification_off() {
+        let cli = Cli::try_parse_from(["permafrost", "compile", "a.osts", "--no-verify"]).unwrap();
+        let Some(Command::Compile(args)) = cli.command else { panic!("expected compile") };
+        assert!(!args.options().verify);
+    }
+
+    #[test]
+    fn parser_backend_defaults_to_legacy() {
+        let cli = Cli::try_parse_from(["permafrost", "compile", "a.osts"]).unwrap();
+        let Some(Command::Compile(args)) = cli.command else { panic!("expected compile") };
+        assert_eq!(args.options().parser, ParserBackend::Legacy);
+
+        let cli = Cli::try_parse_from(["permafrost", "compile", "a.osts", "--parser", "ast"]).unwrap();
+        let Some(Command::Compile(args)) = cli.command else { panic!("expected compile") };
+        assert_eq!(args.options().parser, ParserBackend::Ast);
+    }
+
+    #[test]
+    fn parses_explain_with_optional_symbol() {
+        let cli = Cli::try_parse_from(["permafrost", "explain", "a.osts", "merge"]).unwrap();
+        let Some(Command::Explain(args)) = cli.command else { panic!("expected explain") };
+        assert_eq!(args.path, PathBuf::from("a.osts"));
+        assert_eq!(args.symbol.as_deref(), Some("merge"));
+
+        let cli = Cli::try_parse_from(["permafrost", "explain", "a.osts"]).unwrap();
+        let Some(Command::Explain(args)) = cli.command else { panic!("expected explain") };
+        assert!(args.symbol.is_none());
+        assert!(!args.parser.options().verify);
+    }
+
+    #[test]
+    fn graph_format_defaults_to_dot() {
+        let cli = Cli::try_parse_from(["permafrost", "graph", "a.osts"]).unwrap();
+        let Some(Command::Graph(args)) = cli.command else { panic!("expected graph") };
+        assert_eq!(args.format, GraphFormat::Dot);
+
+        let cli = Cli::try_parse_from(["permafrost", "graph", "a.osts", "--format", "mermaid"]).unwrap();
+        let Some(Command::Graph(args)) = cli.command else { panic!("expected graph") };
+        assert_eq!(args.format, GraphFormat::Mermaid);
+    }
+
+    #[test]
+    fn parses_analyze_lib() {
+        let cli = Cli::try_parse_from(["permafrost", "analyze-lib", "frosts.ts", "--parser", "ast"]).unwrap();
+        let Some(Command::AnalyzeLib(args)) = cli.command else { panic!("expected analyze-lib") };
+        assert_eq!(args.path, PathBuf::from("frosts.ts"));
+        assert_eq!(args.parser.options().parser, ParserBackend::Ast);
+    }
+
+    #[test]
+    fn parses_osts_flag() {
+        let cli = Cli::try_parse_from(["permafrost", "compile", "a.osts", "--osts"]).unwrap();
+        let Some(Command::Compile(args)) = cli.command else { panic!("expected compile") };
+        assert!(args.osts);
+        assert!(args.output.is_none());
+        assert_eq!(args.osts_output(), Some(PathBuf::from("a.min.osts")));
+    }
+
+    #[test]
+    fn osts_output_never_overwrites_the_source() {
+        let cli = Cli::try_parse_from(["permafrost", "compile", "report.min.osts", "--osts"]).unwrap();
+        let Some(Command::Compile(args)) = cli.command else { panic!("expected compile") };
+        assert_eq!(args.osts_output(), None);
+
+        let cli = Cli::try_parse_from(["permafrost", "compile", "report.min.osts", "--osts", "-o", "out.osts"]).unwrap();
+        let Some(Command::Compile(args)) = cli.command else { panic!("expected compile") };
+        assert_eq!(args.osts_output(), Some(PathBuf::from("out.osts")));
+    }
+}
@@ -0,0 +1,986 @@
+//! # compile::code_parser::ast
+//!
+//! An optional usage analyzer built on a full TypeScript parser (`oxc_parser`).
+//!
+//! Rather than matching token patterns, `AstParser` parses the code into a syntax tree
+//! and walks its member and call expressions, resolving each chain back to the
+//! variable, `this` or `new DataFrame(...)` it starts from. Variables are resolved to
+//! their declarations with `oxc_semantic`, so like `FunctionParser` a `df` declared in
+//! one function doesn't leak into another. Code that doesn't parse is handed to the
+//! token-based `FunctionParser` instead.
+
+use std::collections::{HashMap, HashSet};
+
+use oxc_allocator::Allocator;
+use oxc_ast::ast::{
+    Argument, AssignmentExpression, AssignmentOperator, AssignmentTarget, BindingIdentifier, BindingPattern, CallExpression, ChainElement, ComputedMemberExpression, Declaration, Expression, ForOfStatement, ForStatementLeft, FunctionBody, ReturnStatement, FormalParameter, FormalParameters,
+    NewExpression, Program, PropertyDefinition, StaticMemberExpression, TSTypeAnnotation, TSType, TSTypeName, ThisExpression,
+    IdentifierReference, VariableDeclarator,
+};
+use oxc_ast_visit::{walk, Visit};
+use oxc_parser::Parser;
+use oxc_semantic::{Scoping, SemanticBuilder, SymbolId};
+use oxc_span::{GetSpan, SourceType};
+
+use super::{is_known_dataframe_field, record_call_site, CallSites, CallbackParam, CallbackTypes, FunctionParser, UsageAnalyzer, NAMESPACE};
+use crate::compile::graph::utils::{method_key, namespace_key};
+
+/// Finds the DataFrame and `fr` methods called from a piece of code using a TypeScript AST.
+///
+/// Exposes the same `tracking`/`functions` sets and `parse`/`get_methods` API as
+/// `FunctionParser`, so the two backends can be swapped and compared.
+pub struct AstParser {
+    /// Roots tracked everywhere, like `fr` or `this`. After parsing, this also holds the
+    /// name of every variable that was tracked in some scope.
+    pub tracking: HashSet<String>,
+    /// Every member called on a tracked root
+    pub functions: HashSet<String>,
+    /// Members accessed directly on the `fr` namespace
+    pub namespace_calls: HashSet<String>,
+    /// Methods called on DataFrames
+    pub method_calls: HashSet<String>,
+    /// Interface members used on callback parameters, like `("FrostRow", "get_number")`
+    pub interface_calls: HashSet<(String, String)>,
+    /// Where each call was first found
+    pub call_sites: CallSites,
+    /// Callback parameter types of DataFrame methods, see `CallbackTypes`
+    callbacks: CallbackTypes,
+}
+
+impl Default for AstParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AstParser {
+    pub fn new() -> Self {
+        AstParser {
+            tracking: HashSet::new(),
+            functions: HashSet::new(),
+            namespace_calls: HashSet::new(),
+            method_calls: HashSet::new(),
+            interface_calls: HashSet::new(),
+            call_sites: CallSites::new(),
+            callbacks: CallbackTypes::new(),
+        }
+    }
+
+    /// Declares the callback parameter types of DataFrame methods, see `CallbackTypes`.
+    pub fn set_callback_types(&mut self, callbacks: &CallbackTypes) {
+        self.callbacks = callbacks.clone();
+    }
+
+    pub fn parse(&mut self, code: &str, initial_substr: &str) {
+        self.tracking.insert(initial_substr.to_string());
+
+        let allocator = Allocator::default();
+        // DataFrame methods are class members, which only parse inside a class body.
+        // `shift` is how far the wrapper moves the code, to keep call sites in `code`
+        let (program, shift) = match parse_program(&allocator, code) {
+            Some(program) => (Some(program), 0),
+            None => {
+                let wrapped = allocator.alloc_str(&format!("{}{}\n}}", MEMBER_WRAPPER, code));
+                (parse_program(&allocator, wrapped), MEMBER_WRAPPER.len())
+            }
+        };
+
+        let Some(program) = program else {
+            // Fall back to the token-based parser for code oxc can't handle
+            let mut legacy = FunctionParser::new();
+            legacy.set_callback_types(&self.callbacks);
+            legacy.parse(code, initial_substr);
+            self.tracking.extend(legacy.tracking);
+            self.functions.extend(legacy.functions);
+            self.namespace_calls.extend(legacy.namespace_calls);
+            self.method_calls.extend(legacy.method_calls);
+            self.interface_calls.extend(legacy.interface_calls);
+            self.call_sites.extend(legacy.call_sites);
+            return;
+        };
+
+        // Resolve every identifier to the declaration it refers to
+        let scoping = SemanticBuilder::new().build(&program).semantic.into_scoping();
+
+        // 1️⃣ Track variables, parameters and helper functions holding DataFrames. Helpers
+        // can be declared after they're called, so repeat until nothing new is tracked
+        let mut visitor = UsageVisitor {
+            scoping: &scoping,
+            tracking: self.tracking.iter().cloned().map(Binding::Global).collect(),
+            functions: &mut self.functions,
+            namespace_calls: &mut self.namespace_calls,
+            method_calls: &mut self.method_calls,
+            interface_calls: &mut self.interface_calls,
+            call_sites: &mut self.call_sites,
+            shift,
+            callbacks: &self.callbacks,
+            interface_bindings: HashMap::new(),
+            collections: HashSet::new(),
+            record: false,
+        };
+        loop {
+            let tracked = visitor.tracking.len();
+            visitor.visit_program(&program);
+            if visitor.tracking.len() == tracked {
+                break;
+            }
+        }
+
+        // 2️⃣ Record the methods called on them
+        visitor.record = true;
+        visitor.visit_program(&program);
+
+        let names = visitor.tracking.iter().map(|binding| binding.name(&scoping).to_string()).collect::<Vec<_>>();
+        self.tracking.extend(names);
+    }
+
+    pub fn get_methods(&self) -> Vec<String> {
+        self.functions.iter().cloned().collect()
+    }
+}
+
+impl UsageAnalyzer for AstParser {
+    fn parse(&mut self, code: &str, initial_substr: &str) {
+        AstParser::parse(self, code, initial_substr);
+    }
+
+    fn get_methods(&self) -> Vec<String> {
+        AstParser::get_methods(self)
+    }
+
+    fn get_namespace_calls(&self) -> Vec<String> {
+        self.namespace_calls.iter().cloned().collect()
+    }
+
+    fn get_method_calls(&self) -> Vec<String> {
+        self.method_calls.iter().cloned().collect()
+    }
+
+    fn set_callback_types(&mut self, callbacks: &CallbackTypes) {
+        AstParser::set_callback_types(self, callbacks);
+    }
+
+    fn get_interface_calls(&self) -> Vec<(String, String)> {
+        self.interface_calls.iter().cloned().collect()
+    }
+
+    fn get_call_sites(&self) -> CallSites {
+        self.call_sites.clone()
+    }
+}
+
+/// Parses `code` as TypeScript, returning `None` if it has syntax errors.
+fn parse_program<'a>(allocator: &'a Allocator, code: &'a str) -> Option<Program<'a>> {
+    let parsed = Parser::new(allocator, code, SourceType::ts()).parse();
+    (parsed.errors.is_empty() && !parsed.panicked).then_some(parsed.program)
+}
+
+/// Wraps DataFrame methods in a class so they parse.
+const MEMBER_WRAPPER: &str = "class __Member {\n";
+
+/// Methods that store a value into a collection, like `frames.push(df)`.
+const STORE_METHODS: [&str; 3] = ["push", "set", "unshift"];
+
+/// A value that can be tracked: a declared variable, parameter or function, or a name
+/// the code never declares, like `fr`, `this`, a class field like `this.data` or a global.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Binding {
+    Symbol(SymbolId),
+    Global(String),
+}
+
+impl Binding {
+    /// The binding an identifier refers to, following its scope to its declaration.
+    fn of_reference(scoping: &Scoping, ident: &IdentifierReference) -> Self {
+        ident
+            .reference_id
+            .get()
+            .and_then(|reference| scoping.get_reference(reference).symbol_id())
+            .map_or_else(|| Binding::Global(ident.name.to_string()), Binding::Symbol)
+    }
+
+    /// The binding an identifier declares.
+    fn of_declaration(ident: &BindingIdentifier) -> Self {
+        ident.symbol_id.get().map_or_else(|| Binding::Global(ident.name.to_string()), Binding::Symbol)
+    }
+
+    fn name<'s>(&'s self, scoping: &'s Scoping) -> &'s str {
+        match self {
+            Binding::Symbol(symbol) => scoping.symbol_name(*symbol),
+            Binding::Global(name) => name,
+        }
+    }
+}
+
+/// The tracked bindings of a program, with the scopes needed to resolve identifiers to them.
+#[derive(Clone, Copy)]
+struct Tracked<'p> {
+    scoping: &'p Scoping,
+    bindings: &'p HashSet<Binding>,
+}
+
+impl Tracked<'_> {
+    fn contains(&self, ident: &IdentifierReference) -> bool {
+        self.bindings.contains(&Binding::of_reference(self.scoping, ident))
+    }
+
+    fn contains_global(&self, name: &str) -> bool {
+        self.bindings.contains(&Binding::Global(name.to_string()))
+    }
+}
+
+struct UsageVisitor<'p> {
+    scoping: &'p Scoping,
+    /// Bindings holding DataFrames or collections of them
+    tracking: HashSet<Binding>,
+    functions: &'p mut HashSet<String>,
+    namespace_calls: &'p mut HashSet<String>,
+    method_calls: &'p mut HashSet<String>,
+    interface_calls: &'p mut HashSet<(String, String)>,
+    call_sites: &'p mut CallSites,
+    /// Bytes added in front of the parsed code, subtracted from call sites
+    shift: usize,
+    callbacks: &'p CallbackTypes,
+    /// Callback parameters holding an interface, like `row` in `df.query(row => ...)`,
+    /// mapped to the interface's name
+    interface_bindings: HashMap<Binding, String>,
+    /// Collections tracked because DataFrames are stored into them, like `frames` in
+    /// `frames.push(df)`. Storing into them isn't a DataFrame method call
+    collections: HashSet<Binding>,
+    /// False while only collecting tracked variables, true once calls are recorded
+    record: bool,
+}
+
+impl UsageVisitor<'_> {
+    fn tracked(&self) -> Tracked<'_> {
+        Tracked { scoping: self.scoping, bindings: &self.tracking }
+    }
+
+    /// Returns true if the chain `expr` starts from a tracked value, e.g. `df.filter(...)`
+    /// or `this.copy()`. Chains through DataFrame fields like `this.values` don't count.
+    fn is_tracked(&self, expr: &Expression) -> bool {
+        let mut expr = expr;
+        loop {
+            // Type assertions like `(x as fr.DataFrame)` make any value a DataFrame
+            if is_asserted_dataframe(expr) {
+                return true;
+            }
+            // Like `FunctionParser`, a parenthesized expression that mentions a tracked
+            // value is assumed to hold a DataFrame, as in `(await Promise.resolve(df))`
+            if let Expression::ParenthesizedExpression(group) = expr {
+                if self.is_tracked(&group.expression) || self.mentions_tracked(&group.expression) {
+                    return true;
+                }
+            }
+            match expr.get_inner_expression() {
+                Expression::Identifier(ident) => return self.tracked().contains(ident),
+                Expression::StaticMemberExpression(member) if self.is_tracked_field(member) => return true,
+                Expression::ThisExpression(_) => return self.tracked().contains_global("this"),
+                Expression::NewExpression(new) => return is_dataframe_constructor(new),
+                Expression::AwaitExpression(await_expr) => expr = &await_expr.argument,
+                Expression::CallExpression(call) => expr = &call.callee,
+                Expression::ChainExpression(chain) => match &chain.expression {
+                    ChainElement::CallExpression(call) => expr = &call.callee,
+                    ChainElement::TSNonNullExpression(non_null) => expr = &non_null.expression,
+                    element => {
+                        let Some(member) = element.as_member_expression() else {
+                            return false;
+                        };
+                        if member.static_property_name().is_some_and(is_known_dataframe_field) {
+                            return false;
+                        }
+                        expr = member.object();
+                    }
+                },
+                Expression::StaticMemberExpression(member) => {
+                    if is_known_dataframe_field(&member.property.name) {
+                        return false;
+                    }
+                    expr = &member.object;
+                }
+                Expression::ComputedMemberExpression(member) => expr = &member.object,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Records a member accessed on a tracked value, like `head` in `df.head()` or `df["head"]()`,
+    /// whose name starts at byte `start`. Members of a callback's `FrostRow` are recorded as
+    /// uses of that interface instead.
+    fn record_member(&mut self, name: &str, object: &Expression, start: u32) {
+        if !self.record {
+            return;
+        }
+        let site = (start as usize).saturating_sub(self.shift);
+        if let Expression::Identifier(ident) = object.get_inner_expression() {
+            let binding = Binding::of_reference(self.scoping, ident);
+            if STORE_METHODS.contains(&name) && self.collections.contains(&binding) {
+                return;
+            }
+            if let Some(interface) = self.interface_bindings.get(&binding) {
+                if !self.tracking.contains(&binding) {
+                    self.interface_calls.insert((interface.clone(), name.to_string()));
+                    record_call_site(self.call_sites, namespace_key(interface), site);
+                    return;
+                }
+            }
+        }
+        if is_known_dataframe_field(name) || !self.is_tracked(object) {
+            return;
+        }
+        self.functions.insert(name.to_string());
+        if object.get_inner_expression().is_specific_id(NAMESPACE) {
+            self.namespace_calls.insert(name.to_string());
+            record_call_site(self.call_sites, namespace_key(name), site);
+        } else {
+            self.method_calls.insert(name.to_string());
+            record_call_site(self.call_sites, method_key(name), site);
+        }
+    }
+
+    /// Returns true for class fields declared as DataFrames, like `this.data`.
+    fn is_tracked_field(&self, member: &StaticMemberExpression) -> bool {
+        matches!(member.object.get_inner_expression(), Expression::ThisExpression(_))
+            && self.tracked().contains_global(&format!("this.{}", member.property.name))
+    }
+
+    /// Returns true if `expr` references a tracked value anywhere, other than through
+    /// `df.apply(...)`, which returns plain values.
+    fn mentions_tracked(&self, expr: &Expression) -> bool {
+        let mut finder = RootFinder { tracked: self.tracked(), found: false };
+        finder.visit_expression(expr);
+        finder.found && !is_apply_call(expr)
+    }
+
+    /// Returns true if a variable assigned `value` holds DataFrames: a function returning
+    /// them, a type assertion, or any expression that mentions a tracked value.
+    fn holds_dataframe(&self, value: &Expression) -> bool {
+        self.is_dataframe_function(value) || is_asserted_dataframe(value) || self.mentions_tracked(value)
+    }
+
+    /// Returns true if a function returns DataFrames, either by its return type annotation
+    /// or because one of its `return` statements returns a tracked value.
+    fn returns_dataframe(&self, return_type: Option<&TSTypeAnnotation>, body: Option<&FunctionBody>) -> bool {
+        if return_type.is_some() {
+            return is_dataframe_annotation(return_type);
+        }
+        let Some(body) = body else {
+            return false;
+        };
+        let mut finder = ReturnFinder { tracked: self.tracked(), found: false };
+        finder.visit_function_body(body);
+        finder.found
+    }
+
+    /// Like `returns_dataframe`, for functions assigned to variables such as
+    /// `const loadSales = (wb): fr.DataFrame => ...`.
+    fn is_dataframe_function(&self, expr: &Expression) -> bool {
+        match expr.get_inner_expression() {
+            Expression::ArrowFunctionExpression(arrow) => {
+                self.returns_dataframe(arrow.return_type.as_deref(), Some(&arrow.body))
+            }
+            Expression::FunctionExpression(func) => {
+                self.returns_dataframe(func.return_type.as_deref(), func.body.as_deref())
+            }
+            _ => false,
+        }
+    }
+
+    fn track_bindings(&mut self, pattern: &BindingPattern) {
+        for ident in pattern.get_binding_identifiers() {
+            self.tracking.insert(Binding::of_declaration(ident));
+        }
+    }
+
+    fn track_params(&mut self, params: &FormalParameters) {
+        for param in &params.items {
+            self.track_bindings(&param.pattern);
+        }
+    }
+
+    /// Tracks the parameters of a callback by the types frosts declares for them, like a
+    /// `FrostRow` for `row` in `df.query(row => ...)`.
+    fn track_typed_params(&mut self, params: &FormalParameters, types: &[CallbackParam]) {
+        for (param, ty) in params.items.iter().zip(types) {
+            match ty {
+                CallbackParam::DataFrame => self.track_bindings(&param.pattern),
+                CallbackParam::Interface(name) => {
+                    for ident in param.pattern.get_binding_identifiers() {
+                        self.interface_bindings.insert(Binding::of_declaration(ident), name.clone());
+                    }
+                }
+                CallbackParam::Other => {}
+            }
+        }
+    }
+}
+
+impl<'a> Visit<'a> for UsageVisitor<'_> {
+    fn visit_variable_declarator(&mut self, it: &VariableDeclarator<'a>) {
+        let holds_dataframe = it.init.as_ref().is_some_and(|init| self.holds_dataframe(init));
+        if is_dataframe_annotation(it.type_annotation.as_deref()) || holds_dataframe {
+            self.track_bindings(&it.id);
+        }
+        walk::walk_variable_declarator(self, it);
+    }
+
+    /// Reassignments like `q = fr.read_sheet(...)` track the variable in the scope that
+    /// declares it, or as a global if none does.
+    fn visit_assignment_expression(&mut self, it: &AssignmentExpression<'a>) {
+        let is_plain = matches!(it.operator, AssignmentOperator::Assign | AssignmentOperator::LogicalNullish | AssignmentOperator::LogicalOr);
+        if is_plain && self.holds_dataframe(&it.right) {
+            match &it.left {
+                AssignmentTarget::AssignmentTargetIdentifier(ident) => {
+                    self.tracking.insert(Binding::of_reference(self.scoping, ident));
+                }
+                AssignmentTarget::StaticMemberExpression(member)
+                    if matches!(member.object.get_inner_expression(), Expression::ThisExpression(_)) =>
+                {
+                    self.tracking.insert(Binding::Global(format!("this.{}", member.property.name)));
+                }
+                _ => {}
+            }
+        }
+        walk::walk_assignment_expression(self, it);
+    }
+
+    fn visit_declaration(&mut self, it: &Declaration<'a>) {
+        if let Declaration::FunctionDeclaration(func) = it {
+            let returns_dataframe = self.returns_dataframe(func.return_type.as_deref(), func.body.as_deref());
+            if let Some(id) = func.id.as_ref().filter(|_| returns_dataframe) {
+                self.tracking.insert(Binding::of_declaration(id));
+            }
+        }
+        walk::walk_declaration(self, it);
+    }
+
+    fn visit_formal_parameter(&mut self, it: &FormalParameter<'a>) {
+        if is_dataframe_annotation(it.type_annotation.as_deref()) {
+            self.track_bindings(&it.pattern);
+        }
+        walk::walk_formal_parameter(self, it);
+    }
+
+    fn visit_property_definition(&mut self, it: &PropertyDefinition<'a>) {
+        if is_dataframe_annotation(it.type_annotation.as_deref()) {
+            if let Some(name) = it.key.static_name() {
+                self.tracking.insert(Binding::Global(format!("this.{}", name)));
+            }
+        }
+        walk::walk_property_definition(self, it);
+    }
+
+    fn visit_for_of_statement(&mut self, it: &ForOfStatement<'a>) {
+        if let ForStatementLeft::VariableDeclaration(declaration) = &it.left {
+            let mut finder = RootFinder { tracked: self.tracked(), found: false };
+            finder.visit_expression(&it.right);
+            if finder.found {
+                for declarator in &declaration.declarations {
+                    self.track_bindings(&declarator.id);
+                }
+            }
+        }
+        walk::walk_for_of_statement(self, it);
+    }
+
+    fn visit_new_expression(&mut self, it: &NewExpression<'a>) {
+        if self.record && is_dataframe_constructor(it) {
+            self.method_calls.insert("constructor".to_string());
+            // Like `FunctionParser`, the call is at `DataFrame` in `new fr.DataFrame(...)`
+            let start = match it.callee.get_inner_expression() {
+                Expression::StaticMemberExpression(member) => member.property.span.start,
+                callee => callee.span().start,
+            };
+            let site = (start as usize).saturating_sub(self.shift);
+            record_call_site(self.call_sites, method_key("constructor"), site);
+        }
+        walk::walk_new_expression(self, it);
+    }
+
+    fn visit_call_expression(&mut self, it: &CallExpression<'a>) {
+        // Collections that DataFrames are stored into, like `frames.set("sales", df)`
+        if let Expression::StaticMemberExpression(member) = it.callee.get_inner_expression() {
+            if let Expression::Identifier(collection) = member.object.get_inner_expression() {
+                let is_store = STORE_METHODS.contains(&member.property.name.as_str());
+                let mut finder = RootFinder { tracked: self.tracked(), found: false };
+                for arg in &it.arguments {
+                    finder.visit_argument(arg);
+                }
+                if is_store && finder.found {
+                    let binding = Binding::of_reference(self.scoping, collection);
+                    self.collections.insert(binding.clone());
+                    self.tracking.insert(binding);
+                }
+            }
+        }
+
+        if self.record && self.is_tracked(&it.callee) {
+            // Track the parameters of callbacks like `df.apply(row => ...)`
+            let types = called_method(&it.callee).and_then(|name| self.callbacks.get(name));
+            for (index, arg) in it.arguments.iter().enumerate() {
+                let params = match arg {
+                    Argument::ArrowFunctionExpression(arrow) => &arrow.params,
+                    Argument::FunctionExpression(func) => &func.params,
+                    _ => continue,
+                };
+                match types {
+                    Some(types) => self.track_typed_params(params, types.get(index).map_or(&[], Vec::as_slice)),
+                    None => self.track_params(params),
+                }
+            }
+        }
+        walk::walk_call_expression(self, it);
+    }
+
+    fn visit_static_member_expression(&mut self, it: &StaticMemberExpression<'a>) {
+        if !self.is_tracked_field(it) {
+            self.record_member(&it.property.name, &it.object, it.property.span.start);
+        }
+        walk::walk_static_member_expression(self, it);
+    }
+
+    fn visit_computed_member_expression(&mut self, it: &ComputedMemberExpression<'a>) {
+        if let Some(name) = it.static_property_name() {
+            self.record_member(&name, &it.object, it.expression.span().start);
+        }
+        walk::walk_computed_member_expression(self, it);
+    }
+}
+
+/// Looks for any reference to a tracked value inside an expression.
+struct RootFinder<'p> {
+    tracked: Tracked<'p>,
+    found: bool,
+}
+
+impl<'a> Visit<'a> for RootFinder<'_> {
+    fn visit_identifier_reference(&mut self, it: &IdentifierReference<'a>) {
+        self.found |= self.tracked.contains(it);
+    }
+
+    fn visit_this_expression(&mut self, _it: &ThisExpression) {
+        self.found |= self.tracked.contains_global("this");
+    }
+
+    fn visit_new_expression(&mut self, it: &NewExpression<'a>) {
+        // Collections of DataFrames like `new Map<string, fr.DataFrame>()` count too
+        let holds_dataframes = it
+            .type_arguments
+            .as_ref()
+            .is_some_and(|args| args.params.iter().any(is_dataframe_type));
+        self.found |= is_dataframe_constructor(it) || holds_dataframes;
+        walk::walk_new_expression(self, it);
+    }
+}
+
+/// Looks for a `return` statement whose value references a tracked value.
+struct ReturnFinder<'p> {
+    tracked: Tracked<'p>,
+    found: bool,
+}
+
+impl<'a> Visit<'a> for ReturnFinder<'_> {
+    fn visit_return_statement(&mut self, it: &ReturnStatement<'a>) {
+        if let Some(argument) = &it.argument {
+            let mut finder = RootFinder { tracked: self.tracked, found: false };
+            finder.visit_expression(argument);
+            self.found |= finder.found && !is_apply_call(argument);
+        }
+    }
+}
+
+/// Returns the DataFrame method named by a call's callee, like `query` in `df.query(...)`.
+/// Calls on the `fr` namespace return `None`.
+fn called_method<'e>(callee: &'e Expression) -> Option<&'e str> {
+    let member = callee.get_inner_expression().as_member_expression()?;
+    if member.object().get_inner_expression().is_specific_id(NAMESPACE) {
+        return None;
+    }
+    member.static_property_name()
+}
+
+/// Returns true for `new DataFrame(...)` and `new fr.DataFrame(...)`.
+fn is_dataframe_constructor(new: &NewExpression) -> bool {
+    match new.callee.get_inner_expression() {
+        Expression::Identifier(ident) => ident.name == "DataFrame",
+        Expression::StaticMemberExpression(member) => member.property.name == "DataFrame",
+        _ => false,
+    }
+}
+
+/// Returns true for type assertions like `x as fr.DataFrame` or `<DataFrame>x`.
+fn is_asserted_dataframe(expr: &Expression) -> bool {
+    match expr {
+        Expression::ParenthesizedExpression(inner) => is_asserted_dataframe(&inner.expression),
+        Expression::TSNonNullExpression(inner) => is_asserted_dataframe(&inner.expression),
+        Expression::TSAsExpression(cast) => is_dataframe_type(&cast.type_annotation),
+        Expression::TSSatisfiesExpression(cast) => is_dataframe_type(&cast.type_annotation),
+        Expression::TSTypeAssertion(cast) => is_dataframe_type(&cast.type_annotation),
+        _ => false,
+    }
+}
+
+fn is_dataframe_annotation(annotation: Option<&TSTypeAnnotation>) -> bool {
+    annotation.is_some_and(|annotation| is_dataframe_type(&annotation.type_annotation))
+}
+
+/// Returns true for `DataFrame`, `fr.DataFrame` and collections of them like
+/// `DataFrame[]` or `Map<string, DataFrame>`.
+fn is_dataframe_type(ty: &TSType) -> bool {
+    match ty {
+        TSType::TSTypeReference(reference) => {
+            let is_dataframe = match &reference.type_name {
+                TSTypeName::IdentifierReference(ident) => ident.name == "DataFrame",
+                TSTypeName::QualifiedName(qualified) => qualified.right.name == "DataFrame",
+                TSTypeName::ThisExpression(_) => false,
+            };
+            is_dataframe
+                || reference
+                    .type_arguments
+                    .as_ref()
+                    .is_some_and(|args| args.params.iter().any(is_dataframe_type))
+        }
+        TSType::TSArrayType(array) => is_dataframe_type(&array.element_type),
+        _ => false,
+    }
+}
+
+/// `df.apply(...)` returns plain values rather than a DataFrame.
+fn is_apply_call(expr: &Expression) -> bool {
+    match expr.get_inner_expression() {
+        Expression::CallExpression(call) => matches!(
+            call.callee.get_inner_expression(),
+            Expression::StaticMemberExpression(member) if member.property.name == "apply"
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn methods(code: &str, root: &str) -> HashSet<String> {
+        let mut parser = AstParser::new();
+        parser.parse(code, root);
+        parser.functions
+    }
+
+    #[test]
+    fn records_chained_calls() {
+        let found = methods(
+            r#"
+            function main(workbook: ExcelScript.Workbook) {
+                let df = fr.read_csv(workbook);
+                df.filter(row => row.get_number("x") > 2)
+                    .groupBy(["City"], { "Sales": ["sum"] })
+                    .print();
+            }
+            "#,
+            "fr",
+        );
+
+        for name in ["read_csv", "filter", "get_number", "groupBy", "print"] {
+            assert!(found.contains(name), "missing {}", name);
+        }
+    }
+
+    #[test]
+    fn ignores_strings_and_unrelated_objects() {
+        let found = methods(
+            r#"
+            let df = fr.read_csv();
+            const note = "df.melt()";
+            const other = workbook.getWorksheet("df");
+            other.delete();
+            "#,
+            "fr",
+        );
+
+        assert!(found.contains("read_csv"));
+        assert!(!found.contains("melt"));
+        assert!(!found.contains("delete"));
+    }
+
+    #[test]
+    fn parses_class_members_and_skips_fields() {
+        let found = methods(
+            r#"
+            set_column(name: string, values: CellValue[]): DataFrame {
+                let output = this.copy();
+                output.columns.push(name);
+                this.values.map(row => row[name]);
+                return output.__assign(other);
+            }
+            "#,
+            "this",
+        );
+
+        assert!(found.contains("copy"));
+        assert!(found.contains("__assign"));
+        assert!(!found.contains("push"));
+        assert!(!found.contains("map"));
+    }
+
+    #[test]
+    fn tracks_dataframe_parameters_and_new_dataframes() {
+        let found = methods(
+            r#"
+            function combine(left: fr.DataFrame, rows: CellValue[][]) {
+                left.describe();
+                new DataFrame(rows).head();
+            }
+            "#,
+            "fr",
+        );
+
+        assert!(found.contains("describe"));
+        assert!(found.contains("head"));
+    }
+
+    #[test]
+    fn tracks_bindings_by_type_annotation() {
+        let mut parser = AstParser::new();
+        parser.parse(
+            r#"
+            class Report {
+                private data: fr.DataFrame;
+                run() {
+                    this.data.describe();
+                }
+            }
+            function main(workbook: ExcelScript.Workbook) {
+                let sales: fr.DataFrame = load(workbook);
+                sales.merge(other);
+                [sales].forEach((part: DataFrame) => part.print());
+            }
+            "#,
+            "fr",
+        );
+
+        assert!(parser.tracking.contains("this.data"));
+        assert!(parser.tracking.contains("sales"));
+        assert_eq!(parser.method_calls, ["describe", "merge", "print"].iter().map(|s| s.to_string()).collect());
+    }
+
+    #[test]
+    fn tracks_values_returned_from_helper_functions() {
+        let mut parser = AstParser::new();
+        parser.parse(
+            r#"
+            function main(workbook: ExcelScript.Workbook) {
+                let sales = loadSales(workbook);
+                const totals = summarize(workbook);
+                sales.merge(loadRegions(workbook), ["Region"]);
+                totals.print();
+                describe(workbook).length;
+            }
+            function loadSales(wb: ExcelScript.Workbook): fr.DataFrame {
+                return readSheet(wb, "Sales");
+            }
+            function loadRegions(wb: ExcelScript.Workbook) {
+                const df = fr.read_sheet(wb.getWorksheet("Regions"));
+                return df;
+            }
+            const summarize = function (wb: ExcelScript.Workbook) {
+                return loadSales(wb).groupBy(["Region"], { "Sales": ["sum"] });
+            };
+            function describe(wb: ExcelScript.Workbook): string {
+                return wb.getName();
+            }
+            "#,
+            "fr",
+        );
+
+        for tracked in ["sales", "totals", "loadSales", "loadRegions", "summarize"] {
+            assert!(parser.tracking.contains(tracked), "{} should be tracked", tracked);
+        }
+        assert_eq!(parser.method_calls, ["merge", "print", "groupBy"].iter().map(|s| s.to_string()).collect());
+        assert!(!parser.tracking.contains("describe"));
+    }
+
+    #[test]
+    fn tracks_dataframes_stored_in_collections() {
+        let found = methods(
+            r#"
+            const frames = { sales: fr.read_sheet(a), hr: fr.read_sheet(b) };
+            frames.sales.groupBy(["Region"], { "Sales": ["sum"] });
+            const dfs = [frames.sales, frames.hr];
+            dfs[0].concat(dfs[1]);
+            for (const df of dfs) {
+                df.describe();
+            }
+            const lookup = new Map<string, fr.DataFrame>();
+            lookup.get("sales").head();
+            const byName = new Map();
+            byName.set("hr", frames.hr);
+            byName.get("hr").tail();
+            "#,
+            "fr",
+        );
+
+        for method in ["groupBy", "concat", "describe", "head", "tail"] {
+            assert!(found.contains(method), "missing {}", method);
+        }
+    }
+
+    #[test]
+    fn recognises_typescript_chain_syntax() {
+        let mut parser = AstParser::new();
+        parser.parse(
+            r#"
+            async function main(workbook: ExcelScript.Workbook) {
+                let df = fr.read_sheet(workbook);
+                df?.filter(row => true)?.dropna();
+                df!.sortBy("Sales");
+                (other as fr.DataFrame).melt();
+                (<DataFrame>df).pivot("Region");
+                df.apply<number>(row => 1);
+                df["head"]();
+                df?.["tail"]();
+                fr["read_json"]("[]");
+                (await loadSales(workbook)).concat(df);
+            }
+            async function loadSales(wb: ExcelScript.Workbook): Promise<fr.DataFrame> {
+                return fr.read_sheet(wb);
+            }
+            "#,
+            "fr",
+        );
+
+        for method in ["filter", "dropna", "sortBy", "melt", "pivot", "apply", "head", "tail", "concat"] {
+            assert!(parser.method_calls.contains(method), "{} should be called", method);
+        }
+        assert!(parser.namespace_calls.contains("read_json"));
+    }
+
+    #[test]
+    fn separates_namespace_calls_from_method_calls() {
+        let mut parser = AstParser::new();
+        parser.parse("let df = fr.read_csv(text).sum(); fr.sum([1]); new DataFrame([]).head();", "fr");
+
+        assert_eq!(parser.namespace_calls, ["read_csv", "sum"].iter().map(|s| s.to_string()).collect());
+        assert_eq!(parser.method_calls, ["sum", "constructor", "head"].iter().map(|s| s.to_string()).collect());
+    }
+
+    #[test]
+    fn attributes_callback_members_to_their_interface() {
+        let callbacks = CallbackTypes::from([(
+            "query".to_string(),
+            vec![vec![CallbackParam::Interface("FrostRow".to_string())]],
+        )]);
+        let mut parser = AstParser::new();
+        parser.set_callback_types(&callbacks);
+        parser.parse(
+            r#"
+            let df = fr.read_sheet();
+            let adults = df.query(row => row.get_number("Age") >= 18).head();
+            const groups = [df, adults];
+            groups.map(group => group.describe());
+            "#,
+            "fr",
+        );
+
+        assert!(parser.interface_calls.contains(&("FrostRow".to_string(), "get_number".to_string())));
+        for method in ["query", "head", "describe"] {
+            assert!(parser.method_calls.contains(method), "{} should be called", method);
+        }
+        assert!(!parser.method_calls.contains("get_number"));
+    }
+
+    #[test]
+    fn apply_results_are_not_dataframes() {
+        let found = methods(
+            r#"
+            let df = fr.read_csv();
+            let x = df.apply(row => row.get("Income"));
+            x.map(v => v.toString());
+            "#,
+            "fr",
+        );
+
+        assert!(found.contains("apply"));
+        assert!(!found.contains("map"));
+    }
+
+    /// Scripts that exercise scoping and reassignment, which both backends must agree on.
+    const PARITY_FIXTURES: [&str; 5] = [
+        "function main(wb: ExcelScript.Workbook) { let q; q = fr.read_sheet(null); q.tail(); }",
+        r#"
+        function main(wb: ExcelScript.Workbook) {
+            let df = fr.read_sheet(wb);
+            df.head();
+        }
+        function other() {
+            let df = [1, 2, 3];
+            df.concat([4]).sortBy();
+            df.filter(x => x > 1);
+        }
+        "#,
+        r#"
+        async function main(wb: ExcelScript.Workbook) {
+            let df = fr.read_sheet(wb);
+            (await Promise.resolve(df)).describe();
+        }
+        "#,
+        r#"
+        function main(wb: ExcelScript.Workbook) {
+            let df = fr.read_sheet(wb);
+            if (df) {
+                let df = "shadowed";
+                df.split(",");
+            }
+            df.melt();
+        }
+        "#,
+        r#"
+        function main(wb: ExcelScript.Workbook) {
+            let frames = [];
+            let current;
+            for (const sheet of wb.getWorksheets()) {
+                current = fr.read_sheet(sheet);
+                frames.push(current);
+            }
+            frames[0].pivot();
+        }
+        "#,
+    ];
+
+    #[test]
+    fn backends_agree_on_scopes_and_reassignment() {
+        for code in PARITY_FIXTURES {
+            let mut legacy = FunctionParser::new();
+            legacy.parse(code, "fr");
+            let mut ast = AstParser::new();
+            ast.parse(code, "fr");
+
+            assert_eq!(ast.method_calls, legacy.method_calls, "method calls differ for:\n{}", code);
+            assert_eq!(ast.namespace_calls, legacy.namespace_calls, "namespace calls differ for:\n{}", code);
+        }
+    }
+
+    #[test]
+    fn resolves_variables_through_their_scope() {
+        let calls = |code: &str| {
+            let mut parser = AstParser::new();
+            parser.parse(code, "fr");
+            parser.method_calls
+        };
+
+        assert!(calls(PARITY_FIXTURES[0]).contains("tail"));
+        let unrelated = calls(PARITY_FIXTURES[1]);
+        assert!(unrelated.contains("head"));
+        for method in ["concat", "sortBy", "filter"] {
+            assert!(!unrelated.contains(method), "{} is called on an array", method);
+        }
+        assert!(calls(PARITY_FIXTURES[2]).contains("describe"));
+        let shadowed = calls(PARITY_FIXTURES[3]);
+        assert!(shadowed.contains("melt") && !shadowed.contains("split"));
+    }
+
+    #[test]
+    fn falls_back_to_legacy_parser_on_syntax_errors() {
+        let found = methods("let df = fr.read_csv(; df.head()", "fr");
+        assert!(found.contains("read_csv"));
+    }
+}
@@ -1,8 +1,121 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use super::graph::utils::{method_key, namespace_key, Vertex};
+use super::lexer::{code_tokens, matching_close, split_list, statement_end, Token, TokenKind};
+use scope::{expression_end, Scopes};
+
+/// An AST-based analyzer built on a full TypeScript parser
+#[cfg(feature = "ast")]
+pub mod ast;
+
+/// Lexical scopes used to resolve which variables hold DataFrames
+mod scope;
+
+/// A backend that finds which `fr` and DataFrame methods a piece of code calls.
+pub trait UsageAnalyzer {
+    /// Analyzes `code`, starting from the root name `initial_substr` (`fr` or `this`).
+    fn parse(&mut self, code: &str, initial_substr: &str);
+    /// Returns every method found so far.
+    fn get_methods(&self) -> Vec<String>;
+    /// Returns the members accessed directly on the `fr` namespace, like `read_csv`
+    /// in `fr.read_csv(text)`.
+    fn get_namespace_calls(&self) -> Vec<String>;
+    /// Returns the methods called on DataFrames, like `head` in `df.head()`.
+    /// Constructing a `new DataFrame(...)` counts as a call to `constructor`.
+    fn get_method_calls(&self) -> Vec<String>;
+    /// Declares the parameter types frosts passes to callbacks, so members used on a
+    /// callback parameter are attributed to its interface rather than to DataFrame.
+    fn set_callback_types(&mut self, callbacks: &CallbackTypes);
+    /// Returns the interface members used on callback parameters, like
+    /// `("FrostRow", "get_number")` for `df.apply(row => row.get_number("x"))`.
+    fn get_interface_calls(&self) -> Vec<(String, String)>;
+    /// Returns where each call above was first found, see `CallSites`.
+    fn get_call_sites(&self) -> CallSites;
+}
+
+/// The byte offset in the parsed code of the first call to each vertex, like the offset
+/// of `head` in `df.head()` for `fr::DataFrame::head`. Interface members count as calls
+/// to their interface.
+pub type CallSites = HashMap<Vertex, usize>;
+
+/// Records a call to `vertex` at byte `offset`, keeping the earliest call.
+fn record_call_site(sites: &mut CallSites, vertex: Vertex, offset: usize) {
+    sites.entry(vertex).and_modify(|site| *site = (*site).min(offset)).or_insert(offset);
+}
+
+/// The declared type of a callback parameter, like `row` in `(row: FrostRow) => boolean`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallbackParam {
+    DataFrame,
+    /// An interface declared in the `fr` namespace, like `FrostRow`
+    Interface(String),
+    /// Any other type, whose members are never frosts methods
+    Other,
+}
+
+/// The callback parameter types of each DataFrame method, with one entry per argument.
+/// `query(condition: (row: FrostRow) => boolean)` maps `query` to `[[Interface("FrostRow")]]`,
+/// and arguments that aren't callbacks have no parameters.
+pub type CallbackTypes = HashMap<String, Vec<Vec<CallbackParam>>>;
+
+/// The name of the frosts namespace, whose members are namespace items rather than methods.
+pub const NAMESPACE: &str = "fr";
+
+/// Selects the `UsageAnalyzer` used during compilation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ParserBackend {
+    /// The token-based `FunctionParser`
+    #[default]
+    Legacy,
+    /// The TypeScript AST based `AstParser`. Needs the `ast` feature, and falls back
+    /// to `Legacy` in builds without it.
+    Ast,
+}
 
+impl ParserBackend {
+    /// Creates a fresh analyzer for this backend.
+    pub fn analyzer(self) -> Box<dyn UsageAnalyzer> {
+        #[cfg(feature = "ast")]
+        if self == ParserBackend::Ast {
+            return Box::new(ast::AstParser::new());
+        }
+        Box::new(FunctionParser::new())
+    }
+}
+
+/// Finds the DataFrame and `fr` methods called from a piece of code.
+///
+/// Starting from a root name (`fr` for user code, `this` for DataFrame methods), the
+/// parser tracks every variable assigned from a tracked value, then walks each
+/// `tracked.method()` chain in the token stream and records the member names it calls.
+/// Variables are resolved through their lexical scope, so a `df` declared in one function
+/// doesn't leak into another.
 pub struct FunctionParser {
+    /// Roots tracked everywhere, like `fr` or `new DataFrame`. After parsing, this also
+    /// holds every variable that was tracked in some scope.
     pub tracking: HashSet<String>,
+    /// Every member called on a tracked root
     pub functions: HashSet<String>,
+    /// Members accessed directly on the `fr` namespace
+    pub namespace_calls: HashSet<String>,
+    /// Methods called on DataFrames
+    pub method_calls: HashSet<String>,
+    /// Interface members used on callback parameters, like `("FrostRow", "get_number")`
+    pub interface_calls: HashSet<(String, String)>,
+    /// Where each call was first found
+    pub call_sites: CallSites,
+    /// Callback parameter types of DataFrame methods. Without an entry, every callback
+    /// parameter is assumed to hold a DataFrame
+    callbacks: CallbackTypes,
+    /// Interfaces named in `callbacks`, indexed by `Holds::Interface`
+    interfaces: Vec<String>,
+}
+
+impl Default for FunctionParser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FunctionParser {
@@ -12,222 +125,733 @@ impl FunctionParser {
         FunctionParser {
             tracking: tracking_start,
             functions: HashSet::new(),
+            namespace_calls: HashSet::new(),
+            method_calls: HashSet::new(),
+            interface_calls: HashSet::new(),
+            call_sites: CallSites::new(),
+            callbacks: CallbackTypes::new(),
+            interfaces: Vec::new(),
         }
     }
 
-    fn first_pass_assignments(&mut self, code: &str, initial_substr: &str) {
-        self.tracking.insert(initial_substr.to_string());
-
-        for line in code.lines(){
-            let assignments: Vec<&str> = self.tracking
-                .iter()
-                .filter_map(|substr| parse_assignment(line, &substr))
-                .flatten()
-                .collect();
-
-            for a in assignments {
-                if !is_apply_assignment(line) {
-                    self.tracking.insert(a.to_string());
+    /// Declares the callback parameter types of DataFrame methods, see `CallbackTypes`.
+    pub fn set_callback_types(&mut self, callbacks: &CallbackTypes) {
+        for param in callbacks.values().flatten().flatten() {
+            if let CallbackParam::Interface(name) = param {
+                if !self.interfaces.contains(name) {
+                    self.interfaces.push(name.clone());
                 }
             }
         }
+        self.callbacks = callbacks.clone();
     }
 
-    fn second_pass_methods(&mut self, code: &str) {
-        let statements = code.split(';');
-
-        for stmt in statements {
-            let stmt = stmt.trim();
-            if stmt.is_empty() {
-                continue;
+    /// Runs one pass over the code, tracking every variable, helper function and callback
+    /// parameter that holds a DataFrame (or a collection of them) and recording the members
+    /// called on them. Returns true if anything new was tracked.
+    fn track_pass(&mut self, tokens: &[Token], scopes: &mut Scopes) -> bool {
+        let mut changed = false;
+
+        for i in 0..tokens.len() {
+            // 1️⃣ Declarations and reassignments from tracked values
+            if let Some((names, rhs)) = parse_assignment(tokens, i).or_else(|| parse_reassignment(tokens, i)) {
+                let holds = self.evaluate(tokens, scopes, rhs, &mut changed);
+                for name in names {
+                    changed |= scopes.track(i, name, holds);
+                }
             }
 
-            if let Some((base_var, methods)) = self.find_methods_in_chain(stmt) {
-                self.functions.extend(methods.iter().cloned());
-                self.tracking.insert(base_var.to_string());
+            // 2️⃣ Collections that DataFrames are stored into, like `frames.set("sales", df)`
+            if let Some((name, value)) = parse_element_store(tokens, i) {
+                if self.evaluate(tokens, scopes, value, &mut changed) == Holds::DataFrame {
+                    changed |= scopes.track(i, name, Holds::Collection);
+                }
             }
-        }
 
-        //Second pass for finding iterators
-        for tracked in &self.tracking {
-            let pattern = format!("of {}.", tracked);
-            if let Some(pos) = code.find(&pattern) {
-                let after = &code[pos + pattern.len()..];
+            // 3️⃣ Loops over collections, like `for (const df of frames)`
+            if let Some((decl, names, iterable)) = parse_for_of(tokens, i) {
+                if self.evaluate(tokens, scopes, iterable, &mut changed) != Holds::Nothing {
+                    for name in names {
+                        changed |= scopes.track(decl, name, Holds::DataFrame);
+                    }
+                }
+            }
 
-                let method = after
-                    .split(|c| c == '(' || c == ' ' || c == ')' || c == ';')
-                    .next()
-                    .unwrap_or("")
-                    .trim();
+            // 4️⃣ Helper functions returning DataFrames, like `function loadSales(wb): fr.DataFrame`
+            if let Some((name, open)) = function_signature(tokens, i) {
+                let holds = self.returned(tokens, scopes, open, &mut changed);
+                changed |= scopes.track(i, name, holds);
+            }
 
-                if !method.is_empty() && !is_known_dataframe_field(method) {
-                    self.functions.insert(method.to_string());
+            // 5️⃣ Calls on tracked values, including parenthesized ones like `(x as fr.DataFrame).melt()`
+            if let Some((end, root, holds)) = self.resolve_root(tokens, scopes, i) {
+                if root == "new DataFrame" {
+                    self.method_calls.insert("constructor".to_string());
+                    record_call_site(&mut self.call_sites, method_key("constructor"), tokens[end - 1].start);
                 }
+                self.walk_chain(tokens, scopes, end, holds, root == NAMESPACE, &mut changed);
+            } else if let Some((close, holds)) = self.evaluate_group(tokens, scopes, i, &mut changed) {
+                self.walk_chain(tokens, scopes, close + 1, holds, false, &mut changed);
             }
         }
+        changed
     }
 
-    fn clean_methods(&mut self){
-        //Initialize a new empty HashSet
-        let mut new_hash:HashSet<String> = HashSet::new();
-
-        //Trim the hashset so that it doesn't start with "="
-        self.functions
+    /// If a tracked root starts at `tokens[i]`, returns the index just past it, its text and
+    /// what it holds.
+    ///
+    /// Roots given to the parser (like `fr`, `this` or `new DataFrame`) and DataFrame class
+    /// fields match anywhere, preferring the longest so `new DataFrame` wins over a variable
+    /// named `new`. Any other name is resolved through the scope it's declared in.
+    fn resolve_root(&self, tokens: &[Token], scopes: &Scopes, i: usize) -> Option<(usize, String, Holds)> {
+        let global = self.tracking
             .iter()
-            .filter(|func| !func.trim().starts_with("="))
-            .for_each(|f| {
-                new_hash.insert(f.to_string());
-            });
-        self.functions = new_hash;
-    }
-
-    fn find_methods_in_chain(&self, code: &str) -> Option<(String, Vec<String>)> {
-        //Messay and complicated substr matching
-        let mut longest_match = "";
-
-        for tracked in &self.tracking {
-            for (pos, _) in code.match_indices(tracked) {
+            .filter_map(|tracked| Some((match_root(tokens, i, tracked)?, tracked)))
+            .max();
+        if let Some((end, tracked)) = global {
+            return Some((end, tracked.clone(), Holds::DataFrame));
+        }
 
-            let after = code[pos + tracked.len()..].trim_start();
-            let is_boundary_valid = after.starts_with('.') || after.starts_with('[');
+        let tok = tokens.get(i).filter(|t| t.kind == TokenKind::Ident)?;
+        if i > 0 && (tokens[i - 1].is(".") || tokens[i - 1].is("?.")) {
+            return None;
+        }
+        let holds = scopes.lookup(i, tok.text).filter(|holds| *holds != Holds::Nothing)?;
+        Some((i + 1, tok.text.to_string(), holds))
+    }
 
-            if is_token_match(code,pos,tracked) && is_boundary_valid && tracked.len() > longest_match.len() {
-                longest_match = tracked;
+    /// Returns what the expression in `tokens[range]` holds.
+    ///
+    /// Array and object literals holding DataFrames are collections, and chains are
+    /// followed member by member. Any other expression that mentions a tracked value is
+    /// assumed to be a DataFrame, since keeping an extra method is safer than dropping one.
+    fn evaluate(&mut self, tokens: &[Token], scopes: &mut Scopes, range: Range<usize>, changed: &mut bool) -> Holds {
+        let mut lo = range.start;
+        let hi = if range.end > lo && tokens[range.end - 1].is(";") { range.end - 1 } else { range.end };
+        while lo < hi && tokens[lo].is("await") {
+            lo += 1;
+        }
+        if lo >= hi || is_apply_assignment(&tokens[lo..hi]) {
+            return Holds::Nothing;
+        }
+        // Type assertions like `x as fr.DataFrame` decide on their own
+        if let Some(cast) = last_assertion(tokens, lo, hi) {
+            return annotated_holds(tokens, cast + 1);
+        }
+        // Interface values like a `FrostRow` never make an expression a DataFrame
+        let mentions_tracked = (lo..hi)
+            .filter_map(|j| self.resolve_root(tokens, scopes, j))
+            .any(|(_, _, holds)| holds >= Holds::Collection);
+
+        let first = &tokens[lo];
+        if (first.is("[") || first.is("{")) && matching_close(tokens, lo) == Some(hi - 1) {
+            return if mentions_tracked { Holds::Collection } else { Holds::Nothing };
+        }
+        if first.is("new") && is_collection_type(tokens, lo + 1) {
+            return Holds::Collection;
+        }
+        if let Some((end, root, holds)) = self.resolve_root(tokens, scopes, lo) {
+            let (end, holds) = self.walk_chain(tokens, scopes, end, holds, root == NAMESPACE, changed);
+            if end >= hi {
+                return holds;
             }
         }
+        if let Some((close, holds)) = self.evaluate_group(tokens, scopes, lo, changed) {
+            let (end, holds) = self.walk_chain(tokens, scopes, close + 1, holds, false, changed);
+            if end >= hi {
+                return holds;
+            }
         }
 
+        if mentions_tracked {
+            Holds::DataFrame
+        } else {
+            Holds::Nothing
+        }
+    }
 
-        if longest_match.is_empty() {
+    /// If `tokens[open]` starts a parenthesized expression, like `(x as fr.DataFrame)` or
+    /// `(await loadSales(wb))`, returns the index of its `)` and what it holds.
+    fn evaluate_group(&mut self, tokens: &[Token], scopes: &mut Scopes, open: usize, changed: &mut bool) -> Option<(usize, Holds)> {
+        if !tokens.get(open)?.is("(") || is_call_position(tokens, open) {
+            return None;
+        }
+        let close = matching_close(tokens, open)?;
+        // Arrow function parameters aren't expressions
+        if tokens.get(close + 1).is_some_and(|t| t.is("=>") || t.is(":")) {
             return None;
         }
+        Some((close, self.evaluate(tokens, scopes, open + 1..close, changed)))
+    }
 
-        let start = code.find(longest_match)? + longest_match.len();
-        let after = &code[start..];
+    /// Returns what the function whose parameters open at `tokens[open]` returns, either
+    /// from its return type annotation or from what its `return` statements return.
+    fn returned(&mut self, tokens: &[Token], scopes: &mut Scopes, open: usize, changed: &mut bool) -> Holds {
+        let Some(close) = matching_close(tokens, open) else {
+            return Holds::Nothing;
+        };
+        if tokens.get(close + 1).is_some_and(|t| t.is(":")) {
+            return annotated_holds(tokens, close + 2);
+        }
+        if !tokens.get(close + 1).is_some_and(|t| t.is("{")) {
+            return Holds::Nothing;
+        }
 
-        let mut methods = Vec::new();
-        let mut paren_depth = 0;
+        let body_end = matching_close(tokens, close + 1).unwrap_or(tokens.len());
+        let mut holds = Holds::Nothing;
+        for j in close + 2..body_end {
+            if tokens[j].is("return") {
+                let value = j + 1..expression_end(tokens, j + 1);
+                holds = holds.max(self.evaluate(tokens, scopes, value, changed));
+            }
+        }
+        holds
+    }
 
-        for segment in after.split('.') {
-            let trimmed = segment.trim();
-            if trimmed.is_empty() {
+    /// Walks the chain starting at `tokens[i]` on a value holding `holds`, recording every
+    /// member called on a DataFrame, e.g. the `.filter(...).groupBy(...)` following a
+    /// tracked variable. When the chain starts on the `fr` namespace, its first member is a
+    /// namespace item rather than a method. Indexing a collection, or calling one of its
+    /// element methods like `get`, yields a DataFrame. Members of an interface like `FrostRow`
+    /// are recorded as uses of that interface.
+    ///
+    /// Returns the index just past the chain and what the chain evaluates to.
+    fn walk_chain(
+        &mut self,
+        tokens: &[Token],
+        scopes: &mut Scopes,
+        mut i: usize,
+        mut holds: Holds,
+        mut on_namespace: bool,
+        changed: &mut bool,
+    ) -> (usize, Holds) {
+        // The DataFrame method whose arguments may follow, to type its callbacks
+        let mut callee = None;
+        while let Some(tok) = tokens.get(i) {
+            let next = tokens.get(i + 1);
+            // Non-null assertions like `df!.head()`, optional calls like `fn?.()` and explicit
+            // type arguments like `df.apply<number>(...)` don't change the chain
+            if tok.is("!") && next.is_some_and(|t| t.is(".") || t.is("?.") || t.is("[")) {
+                i += 1;
                 continue;
             }
-
-            let base = trimmed
-                .split('(')
-                .next()
-                .unwrap_or("")
-                .trim_end_matches(';')
-                .trim();
-
-            //Skip on DataFrame attributes to avoid map            
-            if !base.is_empty() && is_known_dataframe_field(base){
-                break
+            if tok.is("?.") && next.is_some_and(|t| t.is("(") || t.is("[")) {
+                i += 1;
+                continue;
             }
-            else if !base.is_empty(){
-                methods.push(base.to_string());
+            if let Some(end) = type_arguments_end(tokens, i) {
+                i = end;
+                continue;
             }
-            else{
-                paren_depth += trimmed.matches('(').count();
-                paren_depth -= trimmed.matches(')').count();
 
-                if paren_depth <= 0 && trimmed.ends_with(';') {
+            // Members like `.head` or `["head"]`
+            let member = if tok.is(".") || tok.is("?.") {
+                let Some(member) = next.filter(|t| t.kind == TokenKind::Ident) else {
+                    break;
+                };
+                Some((member.text, i + 2))
+            } else {
+                string_key(tokens, i).map(|key| (key, i + 3))
+            };
+
+            if let Some((member, after)) = member {
+                if holds == Holds::Nothing {
                     break;
                 }
+                // The member's name follows the `.` or `[`
+                let site = tokens[i + 1].start;
+                if let Holds::Interface(index) = holds {
+                    self.interface_calls.insert((self.interfaces[index].clone(), member.to_string()));
+                    record_call_site(&mut self.call_sites, namespace_key(&self.interfaces[index]), site);
+                    holds = Holds::Nothing;
+                    i = after;
+                    continue;
+                }
+                if holds == Holds::Collection {
+                    holds = collection_member(member);
+                    callee = None;
+                    i = after;
+                    continue;
+                }
+                //Skip on DataFrame attributes to avoid map
+                if is_known_dataframe_field(member) {
+                    break;
+                }
+                self.functions.insert(member.to_string());
+                if on_namespace {
+                    self.namespace_calls.insert(member.to_string());
+                    record_call_site(&mut self.call_sites, namespace_key(member), site);
+                } else {
+                    self.method_calls.insert(member.to_string());
+                    record_call_site(&mut self.call_sites, method_key(member), site);
+                }
+                callee = (!on_namespace).then_some(member);
+                on_namespace = false;
+                i = after;
+            } else if tok.is("(") || tok.is("[") {
+                let Some(close) = matching_close(tokens, i) else {
+                    break;
+                };
+                if tok.is("(") {
+                    let types = callee.take().and_then(|name| self.callback_holds(name));
+                    *changed |= track_callback_params(tokens, scopes, i + 1, close, types.as_deref());
+                } else if holds == Holds::Collection {
+                    holds = Holds::DataFrame;
+                }
+                i = close + 1;
+            } else {
+                break;
             }
         }
+        (i, holds)
+    }
 
-        Some((longest_match.to_string(), methods))
+    /// Returns what the parameters of each callback passed to the DataFrame method `name`
+    /// hold, one entry per argument, or `None` if the method's callback types are unknown.
+    fn callback_holds(&self, name: &str) -> Option<Vec<Vec<Holds>>> {
+        let arguments = self.callbacks.get(name)?;
+        let holds = arguments
+            .iter()
+            .map(|params| {
+                params
+                    .iter()
+                    .map(|param| match param {
+                        CallbackParam::DataFrame => Holds::DataFrame,
+                        CallbackParam::Interface(name) => {
+                            Holds::Interface(self.interfaces.iter().position(|i| i == name).unwrap_or_default())
+                        }
+                        CallbackParam::Other => Holds::Nothing,
+                    })
+                    .collect()
+            })
+            .collect();
+        Some(holds)
     }
 
     pub fn parse(&mut self, code: &str, initial_substr: &str){
-        self.first_pass_assignments(code, initial_substr);
-        self.second_pass_methods(code);
-        self.clean_methods();
+        self.tracking.insert(initial_substr.to_string());
+
+        let tokens = code_tokens(code);
+        let mut scopes = Scopes::build(&tokens);
+        self.tracking.extend((0..tokens.len()).filter_map(|i| dataframe_field(&tokens, i)));
+
+        // Tracking only ever grows, and helpers can be declared after they're called or
+        // return DataFrames built by other helpers, so repeat until nothing new is tracked
+        while self.track_pass(&tokens, &mut scopes) {}
+
+        self.tracking.extend(scopes.tracked_names().map(String::from));
     }
 
     pub fn get_methods(&self) -> Vec<String>{
-        return self.functions.iter().map(|s| s.into()).collect();
+        self.functions.iter().map(|s| s.into()).collect()
+    }
+}
+
+impl UsageAnalyzer for FunctionParser {
+    fn parse(&mut self, code: &str, initial_substr: &str) {
+        FunctionParser::parse(self, code, initial_substr);
+    }
+
+    fn get_methods(&self) -> Vec<String> {
+        FunctionParser::get_methods(self)
+    }
+
+    fn get_namespace_calls(&self) -> Vec<String> {
+        self.namespace_calls.iter().cloned().collect()
+    }
+
+    fn get_method_calls(&self) -> Vec<String> {
+        self.method_calls.iter().cloned().collect()
+    }
+
+    fn set_callback_types(&mut self, callbacks: &CallbackTypes) {
+        FunctionParser::set_callback_types(self, callbacks);
+    }
+
+    fn get_interface_calls(&self) -> Vec<(String, String)> {
+        self.interface_calls.iter().cloned().collect()
+    }
+
+    fn get_call_sites(&self) -> CallSites {
+        self.call_sites.clone()
     }
 }
 
-fn parse_assignment<'a>(line: &'a str, substr: &str) -> Option<Vec<&'a str>> {
-    let line = line.trim();
-    if !(line.starts_with("let ") || line.starts_with("const ")) {
+/// Returns every identifier in `code` that isn't a member access, like `detectColumn`
+/// in `detectColumn(values)` but not `copy` in `this.copy()`.
+pub fn bare_identifiers(code: &str) -> HashSet<String> {
+    let tokens = code_tokens(code);
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(i, tok)| tok.kind == TokenKind::Ident && (*i == 0 || !(tokens[i - 1].is(".") || tokens[i - 1].is("?."))))
+        .map(|(_, tok)| tok.text.to_string())
+        .collect()
+}
+
+/// If `tokens[i]` starts a `let`, `const` or `var` declaration with an initializer,
+/// returns the declared names and the token range of the initializer.
+fn parse_assignment<'a>(tokens: &[Token<'a>], i: usize) -> Option<(Vec<&'a str>, Range<usize>)> {
+    if !is_declaration(tokens, i) {
         return None;
     }
 
-    let parts: Vec<&str> = line.splitn(2, '=').collect();
-    if parts.len() != 2 {
+    let end = statement_end(tokens, i);
+    let mut depth = 0usize;
+    let eq = (i + 1..end).find(|&j| {
+        match tokens[j].text {
+            "(" | "[" | "{" | "<" => depth += 1,
+            ")" | "]" | "}" | ">" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        depth == 0 && tokens[j].is("=")
+    })?;
+
+    Some((extract_lhs_variables(&tokens[i + 1..eq])?, eq + 1..end))
+}
+
+/// If `tokens[i]` stores a value into a collection, as in `frames.set("sales", df)`,
+/// `dfs.push(df)`, `dfs[0] = df` or `frames.sales = df`, returns the collection's name and
+/// the token range of the stored value.
+fn parse_element_store<'a>(tokens: &[Token<'a>], i: usize) -> Option<(&'a str, Range<usize>)> {
+    let name = tokens.get(i).filter(|t| t.kind == TokenKind::Ident)?;
+    if i > 0 && (tokens[i - 1].is(".") || tokens[i - 1].is("?.")) {
         return None;
     }
 
-    let lhs = parts[0].trim();
-    let rhs = parts[1].trim();
+    let next = tokens.get(i + 1)?;
+    let key_end = if next.is("[") {
+        matching_close(tokens, i + 1)? + 1
+    } else if next.is(".") && tokens.get(i + 2)?.kind == TokenKind::Ident {
+        i + 3
+    } else {
+        return None;
+    };
+
+    let after = tokens.get(key_end)?;
+    if after.is("=") {
+        return Some((name.text, key_end + 1..expression_end(tokens, key_end + 1)));
+    }
+    let is_store = next.is(".") && ["push", "set", "unshift"].contains(&tokens[i + 2].text);
+    if is_store && after.is("(") {
+        let close = matching_close(tokens, key_end)?;
+        // The value is the last argument, so `set(key, df)` stores `df`
+        let value = (key_end + 1..close).rev().find(|&j| tokens[j].is(",")).map_or(key_end + 1, |comma| comma + 1);
+        return Some((name.text, value..close));
+    }
+    None
+}
 
-    if !rhs.contains(substr) {
+/// If `tokens[i]` starts a `for (const x of xs)` loop, returns the index of the declaration,
+/// the names it declares and the token range of the iterated expression.
+fn parse_for_of<'a>(tokens: &[Token<'a>], i: usize) -> Option<(usize, Vec<&'a str>, Range<usize>)> {
+    if !tokens.get(i)?.is("for") || !tokens.get(i + 1)?.is("(") || !is_declaration(tokens, i + 2) {
         return None;
     }
+    let close = matching_close(tokens, i + 1)?;
+    let of = (i + 3..close).find(|&j| tokens[j].is("of"))?;
+    let names = extract_lhs_variables(&tokens[i + 3..of])?;
+    Some((i + 2, names, of + 1..close))
+}
 
-    extract_lhs_variables(lhs)
+/// If `tokens[i]` reassigns a variable, as in `df = other.filter(...)`, returns its name
+/// and the token range of the assigned value.
+fn parse_reassignment<'a>(tokens: &[Token<'a>], i: usize) -> Option<(Vec<&'a str>, Range<usize>)> {
+    let name = tokens.get(i).filter(|t| t.kind == TokenKind::Ident)?;
+    let previous = i.checked_sub(1).map(|p| &tokens[p]);
+    if previous.is_some_and(|p| [".", "?.", "let", "const", "var"].contains(&p.text)) || !tokens.get(i + 1)?.is("=") {
+        return None;
+    }
+    Some((vec![name.text], i + 2..expression_end(tokens, i + 2)))
 }
 
+/// Returns true if `tokens[i]` starts a `let`, `const` or `var` declaration.
+fn is_declaration(tokens: &[Token], i: usize) -> bool {
+    tokens.get(i).is_some_and(|t| t.is("let") || t.is("const") || t.is("var"))
+        && (i == 0 || !(tokens[i - 1].is(".") || tokens[i - 1].is("?.")))
+}
 
-/// Returns true if the method reference is actually a known DataFrame field,
-/// such as `this.values`, `this.columns`, etc.
-pub fn is_known_dataframe_field(s: &str) -> bool {
-    ["values", "columns", "dtypes", "__headers"]
-        .iter()
-        .any(|field| s.contains(field))
+/// What a tracked binding or expression holds. Ordered so that joining two possibilities
+/// with `max` keeps the more conservative one, since treating a collection as a DataFrame
+/// at worst keeps an extra method.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+enum Holds {
+    #[default]
+    Nothing,
+    /// A value of an interface declared in the `fr` namespace, like the `FrostRow` passed to
+    /// `query` callbacks, indexed into `FunctionParser::interfaces`
+    Interface(usize),
+    /// An array, object or Map of DataFrames
+    Collection,
+    DataFrame,
 }
 
-fn is_apply_assignment(line: &str) -> bool {
-    line.contains(".apply(")
+/// Returns what a binding annotated as a DataFrame, like `sales: fr.DataFrame`, or as a
+/// collection of them, like `dfs: DataFrame[]`, holds.
+fn dataframe_binding(tokens: &[Token], i: usize) -> Holds {
+    if !tokens.get(i).is_some_and(|t| t.kind == TokenKind::Ident) {
+        return Holds::Nothing;
+    }
+    let mut j = i + 1;
+    if tokens.get(j).is_some_and(|t| t.is("?") || t.is("!")) {
+        j += 1;
+    }
+    if !tokens.get(j).is_some_and(|t| t.is(":")) {
+        return Holds::Nothing;
+    }
+    annotated_holds(tokens, j + 1)
 }
 
-/// Parses the left-hand side of an assignment and returns the declared variable names.
-/// Supports both single variables and array destructuring like `[a, b]`.
-fn extract_lhs_variables(lhs: &str) -> Option<Vec<&str>> {
-    let lhs_vars = lhs
-        .strip_prefix("let")
-        .or_else(|| lhs.strip_prefix("const"))?
-        .trim();
+/// If `tokens[i]` is a class field annotated as a DataFrame, like `private data: DataFrame;`,
+/// returns the root to track for it, e.g. `this.data`.
+fn dataframe_field(tokens: &[Token], i: usize) -> Option<String> {
+    let previous = i.checked_sub(1).map(|p| &tokens[p]);
+    let is_local = previous.is_some_and(|p| ["(", ",", "let", "const", "var"].contains(&p.text));
+    (!is_local && dataframe_binding(tokens, i) != Holds::Nothing).then(|| format!("this.{}", tokens[i].text))
+}
 
-    if lhs_vars.starts_with('[') && lhs_vars.ends_with(']') {
-        let cleaned = lhs_vars.trim_matches(&['[', ']'][..]);
-        let vars: Vec<&str> = cleaned
-            .split(',')
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
-            .collect();
-        Some(vars)
-    } else {
-        // Single variable fallback
-        let var = lhs_vars.split_whitespace().next()?;
-        Some(vec![var])
+/// Tracks the parameters of arrow functions passed as arguments in `tokens[lo..hi]`, so
+/// calls on `row` in `df.apply(row => row.get_number("x"))` are picked up. `types` gives
+/// what each callback's parameters hold, per argument; without it every parameter is
+/// assumed to hold a DataFrame. Returns true if a parameter was newly tracked.
+fn track_callback_params(tokens: &[Token], scopes: &mut Scopes, lo: usize, hi: usize, types: Option<&[Vec<Holds>]>) -> bool {
+    let mut changed = false;
+    for (argument, (start, end)) in split_list(tokens, lo, hi, false).into_iter().enumerate() {
+        let param_holds = |param: usize| match types {
+            Some(types) => types.get(argument).and_then(|params| params.get(param)).copied().unwrap_or_default(),
+            None => Holds::DataFrame,
+        };
+
+        for i in start..end {
+            if !tokens[i].is("=>") || i == start {
+                continue;
+            }
+            // Parameters are declared in the arrow's body scope
+            let body = if tokens.get(i + 1).is_some_and(|t| t.is("{")) { i + 1 } else { i };
+            let before = &tokens[i - 1];
+            if before.kind == TokenKind::Ident {
+                changed |= scopes.track(body, before.text, param_holds(0));
+            } else if before.is(")") {
+                let Some(open) = (start..i - 1).rev().find(|&j| matching_close(tokens, j) == Some(i - 1)) else {
+                    continue;
+                };
+                for (param, (lo, hi)) in split_list(tokens, open + 1, i - 1, true).into_iter().enumerate() {
+                    for w in tokens[lo - 1..hi].windows(2) {
+                        if matches!(w[0].text, "(" | "," | "[" | "{") && w[1].kind == TokenKind::Ident {
+                            changed |= scopes.track(body, w[1].text, param_holds(param));
+                        }
+                    }
+                }
+            }
+        }
     }
+    changed
 }
 
+/// If `tokens[i]` names a function, as in `function loadSales(` or
+/// `const loadSales = (`, returns its name and the index of its opening parenthesis.
+fn function_signature<'a>(tokens: &[Token<'a>], i: usize) -> Option<(&'a str, usize)> {
+    let name = tokens.get(i).filter(|t| t.kind == TokenKind::Ident)?;
+    let previous = i.checked_sub(1).map(|p| &tokens[p]);
+    if previous.is_some_and(|p| p.is("function")) && tokens.get(i + 1)?.is("(") {
+        return Some((name.text, i + 1));
+    }
 
-fn is_token_match(code: &str, position: usize, tracked: &str) -> bool {
-    let before = &code[..position];
-    let after = &code[position + tracked.len()..];
+    let is_declared = previous.is_some_and(|p| p.is("let") || p.is("const") || p.is("var"));
+    if !is_declared || !tokens.get(i + 1)?.is("=") {
+        return None;
+    }
+    let mut open = i + 2;
+    if tokens.get(open)?.is("async") {
+        open += 1;
+    }
+    if tokens.get(open)?.is("function") {
+        open += 1;
+    }
+    tokens.get(open)?.is("(").then_some((name.text, open))
+}
 
-    let left_char = before.chars().rev().find(|c| !c.is_whitespace());
-    let right_char = after.chars().find(|c| !c.is_whitespace());
+/// Returns true if the type starting at `tokens[i]` is `DataFrame` or `fr.DataFrame`.
+fn is_dataframe_type(tokens: &[Token], i: usize) -> bool {
+    let qualified = tokens.get(i).is_some_and(|t| t.is(NAMESPACE)) && tokens.get(i + 1).is_some_and(|t| t.is("."));
+    let name = if qualified { i + 2 } else { i };
+    tokens.get(name).is_some_and(|t| t.is("DataFrame"))
+}
 
-    let left_valid = match left_char {
-        Some(c) => !c.is_alphanumeric() && c != '_',
-        None => true,
-    };
+/// Returns what the type annotation starting at `tokens[i]` holds: `fr.DataFrame` is a
+/// DataFrame, while `DataFrame[]`, `Array<DataFrame>` or `Map<string, fr.DataFrame>` are
+/// collections.
+fn annotated_holds(tokens: &[Token], i: usize) -> Holds {
+    // Async helpers return `Promise<fr.DataFrame>`
+    if tokens.get(i).is_some_and(|t| t.is("Promise")) && tokens.get(i + 1).is_some_and(|t| t.is("<")) {
+        return annotated_holds(tokens, i + 2);
+    }
+    if is_dataframe_type(tokens, i) {
+        let name = if tokens[i].is(NAMESPACE) { i + 2 } else { i };
+        return if tokens.get(name + 1).is_some_and(|t| t.is("[")) { Holds::Collection } else { Holds::DataFrame };
+    }
+    if is_collection_type(tokens, i) {
+        Holds::Collection
+    } else {
+        Holds::Nothing
+    }
+}
 
-    let right_valid = match right_char {
-        Some(c) => !c.is_alphanumeric() && c != '_',
-        None => true,
+/// Returns true if the type starting at `tokens[i]` is a generic collection of DataFrames,
+/// like `Map<string, fr.DataFrame>`.
+fn is_collection_type(tokens: &[Token], i: usize) -> bool {
+    const COLLECTIONS: [&str; 5] = ["Array", "ReadonlyArray", "Map", "Set", "Record"];
+    if !tokens.get(i).is_some_and(|t| COLLECTIONS.contains(&t.text)) || !tokens.get(i + 1).is_some_and(|t| t.is("<")) {
+        return false;
+    }
+    let mut depth = 0usize;
+    for j in i + 1..tokens.len() {
+        match tokens[j].text {
+            "<" => depth += 1,
+            ">" => {
+                depth -= 1;
+                if depth == 0 {
+                    return false;
+                }
+            }
+            _ if is_dataframe_type(tokens, j) => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Returns the index of the last `as` outside any brackets in `tokens[lo..hi]`.
+fn last_assertion(tokens: &[Token], lo: usize, hi: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut found = None;
+    for (i, tok) in tokens.iter().enumerate().take(hi).skip(lo) {
+        match tok.text {
+            "(" | "[" | "{" if tok.kind == TokenKind::Punct => depth += 1,
+            ")" | "]" | "}" if tok.kind == TokenKind::Punct => depth = depth.saturating_sub(1),
+            "as" if depth == 0 && tok.kind == TokenKind::Ident => found = Some(i),
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Returns true if the `(` at `tokens[open]` calls whatever precedes it, rather than
+/// grouping an expression.
+fn is_call_position(tokens: &[Token], open: usize) -> bool {
+    const EXPRESSION_KEYWORDS: [&str; 12] =
+        ["return", "await", "typeof", "in", "of", "case", "yield", "void", "delete", "else", "do", "throw"];
+    let Some(previous) = open.checked_sub(1).map(|p| &tokens[p]) else {
+        return false;
     };
+    match previous.kind {
+        TokenKind::Ident => !EXPRESSION_KEYWORDS.contains(&previous.text),
+        TokenKind::Punct => previous.is(")") || previous.is("]") || previous.is(">"),
+        TokenKind::Template => true,
+        _ => false,
+    }
+}
 
-    left_valid && right_valid
+/// If `tokens[i]` opens explicit type arguments for a call, like the `<number>` in
+/// `df.apply<number>(...)`, returns the index of the call's `(`.
+pub fn type_arguments_end(tokens: &[Token], i: usize) -> Option<usize> {
+    if !tokens.get(i)?.is("<") {
+        return None;
+    }
+    let mut depth = 0usize;
+    for (j, tok) in tokens.iter().enumerate().skip(i) {
+        match tok.kind {
+            TokenKind::Ident | TokenKind::String | TokenKind::Number => {}
+            TokenKind::Punct => match tok.text {
+                "<" => depth += 1,
+                ">" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return tokens.get(j + 1).filter(|t| t.is("(")).map(|_| j + 1);
+                    }
+                }
+                "." | "," | "[" | "]" | "(" | ")" | "{" | "}" | "|" | "&" | ":" | ";" | "?" | "=>" => {}
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// If `tokens[i]` opens a bracket access with a string key, like `["head"]`, returns the key.
+fn string_key<'a>(tokens: &[Token<'a>], i: usize) -> Option<&'a str> {
+    if !tokens.get(i)?.is("[") || !tokens.get(i + 2)?.is("]") {
+        return None;
+    }
+    let key = tokens.get(i + 1)?;
+    let is_literal = key.kind == TokenKind::String
+        || (key.kind == TokenKind::Template && key.text.len() >= 2 && key.text.ends_with('`'));
+    is_literal.then(|| &key.text[1..key.text.len() - 1])
+}
+
+/// Returns what accessing `member` on a collection of DataFrames yields. Element methods
+/// like `get` or `find` yield a DataFrame, methods like `filter` another collection, and
+/// any other member is a property of an object like `frames.sales`.
+fn collection_member(member: &str) -> Holds {
+    match member {
+        "at" | "get" | "find" | "findLast" | "pop" | "shift" | "reduce" | "reduceRight" => Holds::DataFrame,
+        "filter" | "slice" | "concat" | "sort" | "reverse" | "splice" | "map" | "flatMap" | "values" | "entries" => {
+            Holds::Collection
+        }
+        "forEach" | "some" | "every" | "findIndex" | "indexOf" | "includes" | "join" | "keys" | "has" | "push"
+        | "unshift" | "set" | "delete" | "clear" | "length" | "size" => Holds::Nothing,
+        _ => Holds::DataFrame,
+    }
+}
+
+/// If the tracked root (e.g. `df`, `this.data` or `new DataFrame`) starts at `tokens[i]`,
+/// returns the index just past it. Member accesses like `other.df` don't count.
+fn match_root(tokens: &[Token], i: usize, tracked: &str) -> Option<usize> {
+    if i > 0 && (tokens[i - 1].is(".") || tokens[i - 1].is("?.")) {
+        return None;
+    }
+
+    let mut end = i;
+    for word in code_tokens(tracked) {
+        if tokens.get(end)?.text != word.text {
+            return None;
+        }
+        end += 1;
+    }
+    Some(end)
+}
+
+/// Returns true if the member name is a known DataFrame field,
+/// such as `this.values`, `this.columns`, etc.
+pub fn is_known_dataframe_field(s: &str) -> bool {
+    ["values", "columns", "dtypes", "__headers"].contains(&s)
+}
+
+fn is_apply_assignment(statement: &[Token]) -> bool {
+    statement
+        .windows(3)
+        .any(|w| w[0].is(".") && w[1].is("apply") && w[2].is("("))
+}
+
+/// Returns the variable names declared by the left-hand side of an assignment.
+/// Supports single variables as well as array and object destructuring like `[a, b]`.
+fn extract_lhs_variables<'a>(lhs: &[Token<'a>]) -> Option<Vec<&'a str>> {
+    let first = lhs.first()?;
+
+    if first.is("[") || first.is("{") {
+        let close = matching_close(lhs, 0)?;
+        let vars = lhs[1..=close]
+            .windows(2)
+            .filter(|w| w[0].kind == TokenKind::Ident && matches!(w[1].text, "," | "]" | "}" | "="))
+            .map(|w| w[0].text)
+            .collect();
+        Some(vars)
+    } else if first.kind == TokenKind::Ident {
+        Some(vec![first.text])
+    } else {
+        None
+    }
 }
 
 #[cfg(test)]
@@ -236,8 +860,10 @@ mod tests {
 
     #[test]
     fn test_parse_assignment() {
-        let line = "let df = fr.read_sheet();";
-        assert_eq!(parse_assignment(line,"fr"), Some(vec!["df"]));
+        let tokens = code_tokens("let df = fr.read_sheet();");
+        let (names, rhs) = parse_assignment(&tokens, 0).unwrap();
+        assert_eq!(names, vec!["df"]);
+        assert_eq!(tokens[rhs.start].text, "fr");
     }
 
     #[test]
@@ -357,7 +983,7 @@ mod tests {
         let mut parser = FunctionParser::new();
         parser.parse(code, "fr");
 
-        assert_eq!(parser.functions.is_empty(), true);
+        assert!(parser.functions.is_empty());
     }
 
     #[test]
@@ -425,6 +1051,21 @@ mod tests {
         assert!(parser.functions.contains("groupBy"));
     }
 
+    #[test]
+    fn chains_continue_past_methods_named_like_fields() {
+        let code = r#"
+            let df = fr.read_sheet();
+            df.get_columns("a").sortBy({"a": false}).tail();
+            let n = df.columns.length;
+        "#;
+        let mut parser = FunctionParser::new();
+        parser.parse(code, "fr");
+
+        assert!(parser.functions.contains("get_columns"));
+        assert!(parser.functions.contains("tail"));
+        assert!(!parser.functions.contains("columns"));
+    }
+
     #[test]
     fn parses_multiline_arguments_in_chain() {
         let code = r#"
@@ -440,8 +1081,7 @@ mod tests {
         "#;
 
         let mut parser = FunctionParser::new();
-        parser.tracking.insert("df".to_string());
-        parser.second_pass_methods(code);
+        parser.parse(code, "df");
 
         assert!(parser.functions.contains("groupBy"));
         assert!(parser.functions.contains("rename"));
@@ -457,8 +1097,7 @@ mod tests {
         "#;
 
         let mut parser = FunctionParser::new();
-        parser.tracking.insert("df".to_string());
-        parser.second_pass_methods(code);
+        parser.parse(code, "df");
 
         assert!(parser.functions.contains("groupBy"));
         assert!(parser.functions.contains("print"));
@@ -471,8 +1110,7 @@ mod tests {
         "#;
 
         let mut parser = FunctionParser::new();
-        parser.tracking.insert("df".to_string());
-        parser.second_pass_methods(code);
+        parser.parse(code, "df");
 
         assert!(parser.functions.contains("set_col"));
         assert!(parser.functions.contains("apply"));
@@ -578,6 +1216,269 @@ mod tests {
         assert!(parser.functions.contains("filter"));
     }
 
+    #[test]
+    fn tracks_dataframe_parameters() {
+        let code = r#"
+            private __assign_inplace(other: DataFrame, inplace: boolean) {
+                this.__assign_properties(...other.__extract_properties());
+            }
+        "#;
+        let mut parser = FunctionParser::new();
+        parser.parse(code, "this");
+
+        assert!(parser.functions.contains("__assign_properties"));
+        assert!(parser.functions.contains("__extract_properties"));
+    }
+
+    #[test]
+    fn ignores_calls_inside_strings_and_comments() {
+        let code = r#"
+            let df = fr.read_csv();
+            const note = `df.melt() is ${df.shape()}`;
+            // df.pivot()
+            return df.head();
+        "#;
+        let mut parser = FunctionParser::new();
+        parser.parse(code, "fr");
+
+        assert!(parser.functions.contains("shape"));
+        assert!(parser.functions.contains("head"));
+        assert!(!parser.functions.contains("melt"));
+        assert!(!parser.functions.contains("pivot"));
+    }
+
+    #[test]
+    fn separates_namespace_calls_from_method_calls() {
+        let code = r#"
+            let df = fr.read_csv(text).sum();
+            let total = fr.sum([1, 2]);
+            new DataFrame([]).head();
+        "#;
+        let mut parser = FunctionParser::new();
+        parser.parse(code, "fr");
+
+        assert_eq!(parser.namespace_calls, ["read_csv", "sum"].iter().map(|s| s.to_string()).collect());
+        assert_eq!(parser.method_calls, ["sum", "constructor", "head"].iter().map(|s| s.to_string()).collect());
+    }
+
+    #[test]
+    fn tracks_bindings_by_type_annotation() {
+        let code = r#"
+            class Report {
+                private data: fr.DataFrame;
+                summary?: DataFrame;
+                run() {
+                    this.data.describe();
+                }
+            }
+            function clean(df: fr.DataFrame): fr.DataFrame {
+                return df.dropna();
+            }
+            function main(workbook: ExcelScript.Workbook) {
+                let sales: fr.DataFrame = load(workbook);
+                let parts: DataFrame[] = [];
+                sales.merge(parts[0]);
+                parts.forEach((part: fr.DataFrame) => part.print());
+            }
+        "#;
+        let mut parser = FunctionParser::new();
+        parser.parse(code, "fr");
+
+        for tracked in ["this.data", "this.summary", "df", "sales", "parts", "part"] {
+            assert!(parser.tracking.contains(tracked), "{} should be tracked", tracked);
+        }
+        for method in ["describe", "dropna", "merge", "print"] {
+            assert!(parser.method_calls.contains(method), "{} should be called", method);
+        }
+        assert!(!parser.tracking.contains("workbook"));
+    }
+
+    #[test]
+    fn tracks_values_returned_from_helper_functions() {
+        let code = r#"
+            function main(workbook: ExcelScript.Workbook) {
+                let sales = loadSales(workbook);
+                let regions = loadRegions(workbook);
+                const totals = summarize(workbook);
+                sales.merge(regions, ["Region"]);
+                totals.print();
+                describe(workbook).length;
+            }
+            function loadSales(wb: ExcelScript.Workbook): fr.DataFrame {
+                return readSheet(wb, "Sales");
+            }
+            function loadRegions(wb: ExcelScript.Workbook) {
+                const df = fr.read_sheet(wb.getWorksheet("Regions"));
+                return df;
+            }
+            const summarize = (wb: ExcelScript.Workbook) => {
+                return loadSales(wb).groupBy(["Region"], { "Sales": ["sum"] });
+            };
+            function describe(wb: ExcelScript.Workbook): string {
+                return wb.getName();
+            }
+        "#;
+        let mut parser = FunctionParser::new();
+        parser.parse(code, "fr");
+
+        for tracked in ["sales", "regions", "totals", "loadSales", "loadRegions", "summarize"] {
+            assert!(parser.tracking.contains(tracked), "{} should be tracked", tracked);
+        }
+        for method in ["merge", "print", "groupBy", "read_sheet"] {
+            assert!(parser.functions.contains(method), "{} should be called", method);
+        }
+        assert!(!parser.tracking.contains("describe"));
+        assert!(!parser.functions.contains("length"));
+    }
+
+    #[test]
+    fn tracking_is_scoped_to_functions_and_blocks() {
+        let code = r#"
+            function load(wb: ExcelScript.Workbook) {
+                let rows = fr.read_sheet(wb);
+                rows.melt();
+            }
+            function count(values: number[]) {
+                let rows = values.slice(1);
+                rows.reverse();
+                if (rows.length) {
+                    const df = fr.read_json("[]");
+                    df.head();
+                }
+                const df = [1, 2];
+                df.pop();
+                for (const rows of [[1]]) {
+                    rows.shift();
+                }
+                values.forEach(({ rows }) => rows.splice(0));
+            }
+        "#;
+        let mut parser = FunctionParser::new();
+        parser.parse(code, "fr");
+
+        assert!(parser.functions.contains("melt"));
+        assert!(parser.functions.contains("head"));
+        for method in ["reverse", "pop", "shift", "splice"] {
+            assert!(!parser.functions.contains(method), "{} isn't called on a DataFrame", method);
+        }
+    }
+
+    #[test]
+    fn tracks_reassignments_and_var_declarations() {
+        let code = r#"
+            let df;
+            df = fr.read_csv(text);
+            if (df) {
+                var grouped = df.groupBy(["City"], { "Sales": ["sum"] });
+            }
+            grouped.print();
+            let summary = [];
+            summary = grouped.describe();
+            summary.to_csv();
+        "#;
+        let mut parser = FunctionParser::new();
+        parser.parse(code, "fr");
+
+        for method in ["read_csv", "groupBy", "print", "describe", "to_csv"] {
+            assert!(parser.functions.contains(method), "{} should be called", method);
+        }
+    }
+
+    #[test]
+    fn tracks_dataframes_stored_in_collections() {
+        let code = r#"
+            const frames = { sales: fr.read_sheet(a), hr: fr.read_sheet(b) };
+            frames.sales.groupBy(["Region"], { "Sales": ["sum"] });
+            const dfs = [frames.sales, frames.hr];
+            dfs[0].concat(dfs[1]);
+            dfs.map(d => d.drop("x")).forEach(d => d.print());
+            dfs.filter(d => d.shape[0] > 0).length;
+            const total = dfs.reduce((acc, d) => acc.merge(d));
+            for (const df of dfs) {
+                df.describe();
+            }
+            const lookup = new Map<string, fr.DataFrame>();
+            lookup.get("sales").head();
+            const byName = new Map();
+            byName.set("hr", frames.hr);
+            byName.get("hr").tail();
+        "#;
+        let mut parser = FunctionParser::new();
+        parser.parse(code, "fr");
+
+        for method in ["groupBy", "concat", "drop", "print", "merge", "describe", "head", "tail"] {
+            assert!(parser.method_calls.contains(method), "{} should be called", method);
+        }
+        for member in ["sales", "hr", "map", "forEach", "filter", "reduce", "get", "set", "length"] {
+            assert!(!parser.method_calls.contains(member), "{} isn't a DataFrame method call", member);
+        }
+        assert!(parser.tracking.contains("total"));
+    }
+
+    #[test]
+    fn recognises_typescript_chain_syntax() {
+        let code = r#"
+            async function main(workbook: ExcelScript.Workbook) {
+                let df = fr.read_sheet(workbook);
+                df?.filter(row => true)?.dropna();
+                df!.sortBy("Sales");
+                (other as fr.DataFrame).melt();
+                (<DataFrame>df).pivot("Region");
+                df.apply<number>(row => 1);
+                df.map_col<string>("x", v => v).describe();
+                df["head"]();
+                df?.["tail"]();
+                fr["read_json"]("[]");
+                const loaded = await loadSales(workbook);
+                loaded.merge(df);
+                (await loadSales(workbook)).concat(df);
+                const cast = other as fr.DataFrame;
+                cast.rename({});
+            }
+            async function loadSales(wb: ExcelScript.Workbook): Promise<fr.DataFrame> {
+                return fr.read_sheet(wb);
+            }
+        "#;
+        let mut parser = FunctionParser::new();
+        parser.parse(code, "fr");
+
+        for method in [
+            "filter", "dropna", "sortBy", "melt", "pivot", "apply", "map_col", "describe", "head", "tail", "merge",
+            "concat", "rename",
+        ] {
+            assert!(parser.method_calls.contains(method), "{} should be called", method);
+        }
+        assert!(parser.namespace_calls.contains("read_json"));
+    }
+
+    #[test]
+    fn attributes_callback_members_to_their_interface() {
+        let code = r#"
+            let df = fr.read_sheet();
+            let adults = df.query(row => row.get_number("Age") >= 18).head();
+            let taxes = df.apply((row, i) => row.get_number("Income") * 0.3 + i.toFixed());
+            let groups = [df, adults];
+            groups.map(group => group.describe());
+        "#;
+        let frost_row = || vec![CallbackParam::Interface("FrostRow".to_string())];
+        let callbacks = CallbackTypes::from([
+            ("query".to_string(), vec![frost_row()]),
+            ("apply".to_string(), vec![[frost_row(), vec![CallbackParam::Other]].concat()]),
+        ]);
+        let mut parser = FunctionParser::new();
+        parser.set_callback_types(&callbacks);
+        parser.parse(code, "fr");
+
+        assert!(parser.interface_calls.contains(&("FrostRow".to_string(), "get_number".to_string())));
+        for method in ["query", "head", "apply", "describe"] {
+            assert!(parser.method_calls.contains(method), "{} should be called", method);
+        }
+        for member in ["get_number", "toFixed"] {
+            assert!(!parser.method_calls.contains(member), "{} isn't a DataFrame method call", member);
+        }
+        assert!(!parser.tracking.contains("row"));
+    }
+
     #[test]
     fn parser_works_on_fr_iterators(){
         let code = r#"
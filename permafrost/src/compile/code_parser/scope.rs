@@ -0,0 +1,380 @@
+//! # compile::code_parser::scope
+//!
+//! Lexical scopes for the token-based `FunctionParser`.
+//!
+//! Every block, function body, arrow function and `for` header gets its own scope, and
+//! every name declared with `let`, `const`, `var`, `function`, a parameter or a `catch`
+//! clause is recorded in the scope it belongs to. Names start out untracked, so a local
+//! `df` that isn't a DataFrame shadows a tracked `df` further out.
+
+use std::collections::{HashMap, HashSet};
+
+use super::{dataframe_binding, extract_lhs_variables, is_declaration, Holds};
+use crate::compile::lexer::{matching_close, statement_end, Token, TokenKind};
+
+/// Keywords whose parenthesized headers aren't parameter lists.
+const CONTROL_KEYWORDS: [&str; 7] = ["if", "while", "for", "switch", "catch", "with", "return"];
+
+/// Modifiers that can precede a parameter name, like `private` in a constructor.
+const PARAMETER_MODIFIERS: [&str; 5] = ["public", "private", "protected", "readonly", "..."];
+
+struct Scope {
+    parent: Option<usize>,
+    /// Token index where the scope begins
+    start: usize,
+    /// Exclusive token index where the scope ends
+    end: usize,
+    /// Function scopes are where `var` declarations land
+    is_function: bool,
+    /// Every name declared in this scope, and what it currently holds
+    vars: HashMap<String, Holds>,
+}
+
+/// The scopes of a token stream, outermost first.
+pub struct Scopes {
+    scopes: Vec<Scope>,
+    /// The innermost scope containing each token
+    token_scopes: Vec<usize>,
+}
+
+impl Scopes {
+    /// Finds every scope in `tokens` and declares the names bound in each. Bindings
+    /// annotated as DataFrames, like `df: fr.DataFrame`, start out tracked.
+    pub fn build(tokens: &[Token]) -> Self {
+        let mut scopes = Scopes {
+            scopes: vec![Scope {
+                parent: None,
+                start: 0,
+                end: tokens.len(),
+                is_function: true,
+                vars: HashMap::new(),
+            }],
+            token_scopes: vec![0; tokens.len()],
+        };
+
+        // 1️⃣ Parameter lists, with the body of the function each belongs to
+        let mut params: Vec<(usize, usize, usize)> = Vec::new();
+        for i in 0..tokens.len() {
+            let tok = &tokens[i];
+            if tok.is("(") {
+                if let Some((close, body)) = parameter_list(tokens, i) {
+                    params.push((i + 1, close, body));
+                }
+            } else if tok.is("catch") && tokens.get(i + 1).is_some_and(|t| t.is("(")) {
+                if let Some(close) = matching_close(tokens, i + 1) {
+                    params.push((i + 2, close, close + 1));
+                }
+            } else if tok.kind == TokenKind::Ident && tokens.get(i + 1).is_some_and(|t| t.is("=>")) {
+                let body = if tokens.get(i + 2).is_some_and(|t| t.is("{")) { i + 2 } else { i + 1 };
+                params.push((i, i + 1, body));
+            }
+        }
+        let function_bodies: HashSet<usize> = params.iter().map(|&(_, _, body)| body).collect();
+
+        // 2️⃣ Scope boundaries
+        for i in 0..tokens.len() {
+            let tok = &tokens[i];
+            if tok.is("{") {
+                let end = matching_close(tokens, i).map_or(tokens.len(), |close| close + 1);
+                let is_function = function_bodies.contains(&i) || i > 0 && tokens[i - 1].is("=>");
+                scopes.push(i, end, is_function);
+            } else if tok.is("=>") && !tokens.get(i + 1).is_some_and(|t| t.is("{")) {
+                scopes.push(i, expression_end(tokens, i + 1), true);
+            } else if tok.is("for") && tokens.get(i + 1).is_some_and(|t| t.is("(")) {
+                let Some(close) = matching_close(tokens, i + 1) else {
+                    continue;
+                };
+                scopes.push(i + 1, body_end(tokens, close + 1), false);
+            }
+        }
+        scopes.scopes.sort_by_key(|scope| scope.start);
+        scopes.link_parents();
+
+        // 3️⃣ Parameters and `catch` bindings
+        for (lo, hi, body) in params {
+            let scope = scopes.innermost(body);
+            for param in split_parameters(tokens, lo, hi) {
+                let holds = dataframe_binding(tokens, param);
+                scopes.scopes[scope].vars.insert(tokens[param].text.to_string(), holds);
+            }
+            for name in destructured_parameters(tokens, lo, hi) {
+                scopes.scopes[scope].vars.entry(name.to_string()).or_default();
+            }
+        }
+
+        // 4️⃣ Variable and function declarations
+        for i in 0..tokens.len() {
+            if is_declaration(tokens, i) {
+                let scope = if tokens[i].is("var") { scopes.function_scope(i) } else { scopes.innermost(i) };
+                let end = statement_end(tokens, i);
+                let lhs_end = (i + 1..end).find(|&j| tokens[j].is("=")).unwrap_or(end);
+                let holds = dataframe_binding(tokens, i + 1);
+                for name in extract_lhs_variables(&tokens[i + 1..lhs_end]).unwrap_or_default() {
+                    scopes.scopes[scope].vars.insert(name.to_string(), holds);
+                }
+            } else if tokens[i].is("function") {
+                if let Some(name) = tokens.get(i + 1).filter(|t| t.kind == TokenKind::Ident) {
+                    let scope = scopes.function_scope(i);
+                    scopes.scopes[scope].vars.entry(name.text.to_string()).or_default();
+                }
+            }
+        }
+
+        scopes
+    }
+
+    fn push(&mut self, start: usize, end: usize, is_function: bool) {
+        self.scopes.push(Scope { parent: None, start, end, is_function, vars: HashMap::new() });
+    }
+
+    /// Links each scope to the innermost scope enclosing it, and each token to the innermost
+    /// scope containing it. Expects scopes sorted by start.
+    fn link_parents(&mut self) {
+        let mut open: Vec<usize> = Vec::new();
+        for id in 0..self.scopes.len() {
+            let end = self.scopes[id].end.min(self.token_scopes.len());
+            self.token_scopes[self.scopes[id].start..end].fill(id);
+
+            while let Some(&top) = open.last() {
+                if self.scopes[id].start < self.scopes[top].end {
+                    break;
+                }
+                open.pop();
+            }
+            self.scopes[id].parent = open.last().copied();
+            open.push(id);
+        }
+    }
+
+    /// Returns the innermost scope containing `tokens[i]`.
+    pub fn innermost(&self, i: usize) -> usize {
+        self.token_scopes.get(i).copied().unwrap_or(0)
+    }
+
+    /// Returns the innermost function scope containing `tokens[i]`.
+    fn function_scope(&self, i: usize) -> usize {
+        let mut id = self.innermost(i);
+        while !self.scopes[id].is_function {
+            match self.scopes[id].parent {
+                Some(parent) => id = parent,
+                None => break,
+            }
+        }
+        id
+    }
+
+    /// Returns the scope that declares `name` as seen from `tokens[i]`, if any.
+    pub fn declaring_scope(&self, i: usize, name: &str) -> Option<usize> {
+        let mut id = Some(self.innermost(i));
+        while let Some(scope) = id {
+            if self.scopes[scope].vars.contains_key(name) {
+                return Some(scope);
+            }
+            id = self.scopes[scope].parent;
+        }
+        None
+    }
+
+    /// Returns what `name`, as seen from `tokens[i]`, holds, or `None` if no enclosing
+    /// scope declares it.
+    pub fn lookup(&self, i: usize, name: &str) -> Option<Holds> {
+        let scope = self.declaring_scope(i, name)?;
+        self.scopes[scope].vars.get(name).copied()
+    }
+
+    /// Records that `name` holds `holds` in the scope that declares it as seen from
+    /// `tokens[i]`, or in the outermost scope if it was never declared. Returns true if
+    /// that changed what it holds.
+    pub fn track(&mut self, i: usize, name: &str, holds: Holds) -> bool {
+        if holds == Holds::Nothing {
+            return false;
+        }
+        let scope = self.declaring_scope(i, name).unwrap_or(0);
+        let current = self.scopes[scope].vars.entry(name.to_string()).or_default();
+        let joined = (*current).max(holds);
+        std::mem::replace(current, joined) != joined
+    }
+
+    /// Every name tracked as a DataFrame or collection of them in any scope.
+    pub fn tracked_names(&self) -> impl Iterator<Item = &str> {
+        self.scopes
+            .iter()
+            .flat_map(|scope| scope.vars.iter())
+            .filter(|(_, holds)| **holds >= Holds::Collection)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Returns the exclusive end index of the expression starting at `tokens[start]`, such as
+/// the body of `row => row.get("x")` inside a call's arguments.
+pub fn expression_end(tokens: &[Token], start: usize) -> usize {
+    let end = statement_end(tokens, start);
+    let mut depth = 0usize;
+    for (i, tok) in tokens.iter().enumerate().take(end).skip(start) {
+        match tok.text {
+            "(" | "[" | "{" if tok.kind == TokenKind::Punct => depth += 1,
+            ")" | "]" | "}" if tok.kind == TokenKind::Punct => {
+                if depth == 0 {
+                    return i;
+                }
+                depth -= 1;
+            }
+            "," | ";" if depth == 0 => return i,
+            _ => {}
+        }
+    }
+    end
+}
+
+/// Returns the exclusive end of the loop body starting at `tokens[start]`.
+fn body_end(tokens: &[Token], start: usize) -> usize {
+    if tokens.get(start).is_some_and(|t| t.is("{")) {
+        matching_close(tokens, start).map_or(tokens.len(), |close| close + 1)
+    } else {
+        statement_end(tokens, start)
+    }
+}
+
+/// If the `(` at `tokens[open]` starts a parameter list, returns the index of its `)` and
+/// of the function body: the `{` of a block body or the `=>` of an expression body.
+fn parameter_list(tokens: &[Token], open: usize) -> Option<(usize, usize)> {
+    let close = matching_close(tokens, open)?;
+    let previous = open.checked_sub(1).map(|p| &tokens[p]);
+    let is_call_like = previous.is_some_and(|p| {
+        (p.kind == TokenKind::Ident && !CONTROL_KEYWORDS.contains(&p.text)) || p.is(">")
+    });
+
+    let mut i = close + 1;
+    if tokens.get(i).is_some_and(|t| t.is(":")) {
+        // Skip the return type annotation
+        let mut angle = 0usize;
+        i += 1;
+        while let Some(tok) = tokens.get(i) {
+            match tok.text {
+                "<" => angle += 1,
+                ">" => angle = angle.saturating_sub(1),
+                // An object type like `{ name: string }`
+                "{" if i == close + 2 => i = matching_close(tokens, i)?,
+                "{" | "=>" if angle == 0 => break,
+                "(" | "[" => i = matching_close(tokens, i)?,
+                ";" | "}" | ")" => return None,
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    let body = tokens.get(i)?;
+    if body.is("=>") {
+        let body = if tokens.get(i + 1).is_some_and(|t| t.is("{")) { i + 1 } else { i };
+        Some((close, body))
+    } else if body.is("{") && (is_call_like || previous.is_some_and(|p| p.is("function"))) {
+        Some((close, i))
+    } else {
+        None
+    }
+}
+
+/// Returns the index of each plain parameter name in `tokens[lo..hi]`.
+fn split_parameters(tokens: &[Token], lo: usize, hi: usize) -> Vec<usize> {
+    let mut names = Vec::new();
+    let mut depth = 0usize;
+    let mut expecting = true;
+    for (i, tok) in tokens.iter().enumerate().take(hi).skip(lo) {
+        match tok.text {
+            "(" | "[" | "{" | "<" if tok.kind == TokenKind::Punct => depth += 1,
+            ")" | "]" | "}" | ">" if tok.kind == TokenKind::Punct => depth = depth.saturating_sub(1),
+            "," if depth == 0 => {
+                expecting = true;
+                continue;
+            }
+            _ => {}
+        }
+        if expecting && depth == 0 && !PARAMETER_MODIFIERS.contains(&tok.text) && tok.kind == TokenKind::Ident {
+            names.push(i);
+        }
+        if !PARAMETER_MODIFIERS.contains(&tok.text) {
+            expecting = false;
+        }
+    }
+    names
+}
+
+/// Returns the names bound by destructured parameters in `tokens[lo..hi]`,
+/// like `a` and `b` in `([a, b]) => ...`.
+fn destructured_parameters<'a>(tokens: &[Token<'a>], lo: usize, hi: usize) -> Vec<&'a str> {
+    let mut names = Vec::new();
+    let mut i = lo;
+    while i < hi {
+        let starts_pattern = (tokens[i].is("[") || tokens[i].is("{"))
+            && (i == lo || tokens[i - 1].is(",") || tokens[i - 1].is("..."));
+        if !starts_pattern {
+            i += 1;
+            continue;
+        }
+        let Some(close) = matching_close(tokens, i).filter(|&close| close < hi) else {
+            break;
+        };
+        names.extend(extract_lhs_variables(&tokens[i..=close]).unwrap_or_default());
+        i = close + 1;
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::lexer::code_tokens;
+
+    fn position(tokens: &[Token], text: &str, nth: usize) -> usize {
+        tokens.iter().enumerate().filter(|(_, t)| t.text == text).nth(nth).unwrap().0
+    }
+
+    #[test]
+    fn function_parameters_shadow_outer_names() {
+        let code = "let df = 1; function clean(df, [a, b]: number[]) { df.x(); } df.y();";
+        let tokens = code_tokens(code);
+        let scopes = Scopes::build(&tokens);
+
+        let inner = position(&tokens, "x", 0);
+        let outer = position(&tokens, "y", 0);
+        assert_ne!(scopes.declaring_scope(inner, "df"), scopes.declaring_scope(outer, "df"));
+        assert!(scopes.declaring_scope(inner, "a").is_some());
+        assert!(scopes.declaring_scope(outer, "a").is_none());
+    }
+
+    #[test]
+    fn let_is_block_scoped_and_var_is_function_scoped() {
+        let code = "function f() { if (x) { let a = 1; var b = 2; } a; b; }";
+        let tokens = code_tokens(code);
+        let scopes = Scopes::build(&tokens);
+
+        let after = position(&tokens, "a", 1);
+        assert!(scopes.lookup(after, "a").is_none());
+        assert_eq!(scopes.lookup(after, "b"), Some(Holds::Nothing));
+    }
+
+    #[test]
+    fn arrow_and_loop_bindings_stay_local() {
+        let code = "rows.map(row => row.x); for (const df of dfs) { df.y(); } row; df;";
+        let tokens = code_tokens(code);
+        let scopes = Scopes::build(&tokens);
+
+        assert!(scopes.lookup(position(&tokens, "x", 0) - 2, "row").is_some());
+        assert!(scopes.lookup(position(&tokens, "y", 0), "df").is_some());
+        assert!(scopes.lookup(position(&tokens, "row", 2), "row").is_none());
+        assert!(scopes.lookup(position(&tokens, "df", 2), "df").is_none());
+    }
+
+    #[test]
+    fn annotated_bindings_start_tracked() {
+        let code = "function clean(df: fr.DataFrame, n: number) { let out: DataFrame = load(); }";
+        let tokens = code_tokens(code);
+        let scopes = Scopes::build(&tokens);
+
+        let body = position(&tokens, "load", 0);
+        assert_eq!(scopes.lookup(body, "df"), Some(Holds::DataFrame));
+        assert_eq!(scopes.lookup(body, "n"), Some(Holds::Nothing));
+        assert_eq!(scopes.lookup(body, "out"), Some(Holds::DataFrame));
+    }
+}
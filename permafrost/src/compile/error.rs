@@ -0,0 +1,155 @@
+//! # compile::error
+//!
+//! Defines `CompileError`, the single error type returned by every stage of the
+//! permafrost pipeline, from reading the `.osts` file to emitting the compiled script.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Errors raised while reading, compiling or writing a frosts script.
+///
+/// Variants carry the originating file (when known) and a 1-based line number
+/// so callers can point users at the offending part of their script.
+#[derive(Debug)]
+pub enum CompileError {
+    /// The script does not contain a `namespace fr { ... }` block
+    MissingNamespace { file: Option<PathBuf> },
+    /// The `fr` namespace has no `DataFrame` class with a constructor. `line` is where
+    /// the namespace starts
+    MissingConstructor { file: Option<PathBuf>, line: usize },
+    /// A required method is not defined in the `fr` namespace. `line` is where main uses
+    /// the code that needs it, when it was reached from main
+    UnknownMethod { name: String, file: Option<PathBuf>, line: Option<usize> },
+    /// The `.osts` file is not valid JSON or is missing required fields
+    InvalidOsts { file: Option<PathBuf>, line: usize, column: usize, message: String },
+    /// The compiled script uses a symbol that compilation removed. `line` is the
+    /// 1-based line of the use in the compiled output, not in `file`
+    UnresolvedReference { name: String, file: Option<PathBuf>, line: usize },
+    /// A `permafrost.toml` config is not valid TOML or has unknown keys
+    InvalidConfig { file: PathBuf, message: String },
+    /// A file could not be read or written
+    Io { file: PathBuf, source: io::Error },
+}
+
+impl CompileError {
+    /// Attaches the file being compiled to errors that don't know it yet.
+    pub fn with_file(mut self, path: &Path) -> Self {
+        match &mut self {
+            CompileError::MissingNamespace { file }
+            | CompileError::MissingConstructor { file, .. }
+            | CompileError::UnknownMethod { file, .. }
+            | CompileError::InvalidOsts { file, .. }
+            | CompileError::UnresolvedReference { file, .. } => {
+                file.get_or_insert_with(|| path.to_path_buf());
+            }
+            CompileError::InvalidConfig { .. } | CompileError::Io { .. } => {}
+        }
+        self
+    }
+
+    /// Returns true if the error came from the filesystem rather than the script itself.
+    pub fn is_io(&self) -> bool {
+        matches!(self, CompileError::Io { .. })
+    }
+}
+
+/// Formats an optional `file:line: ` prefix for error messages.
+fn location(file: &Option<PathBuf>, line: Option<usize>) -> String {
+    match (file, line) {
+        (Some(file), Some(line)) => format!("{}:{}: ", file.display(), line),
+        (Some(file), None) => format!("{}: ", file.display()),
+        (None, Some(line)) => format!("line {}: ", line),
+        (None, None) => String::new(),
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::MissingNamespace { file } => write!(
+                f,
+                "{}no `namespace fr` block found. Is frosts pasted above your main function?",
+                location(file, None)
+            ),
+            CompileError::MissingConstructor { file, line } => write!(
+                f,
+                "{}the `fr` namespace has no DataFrame constructor",
+                location(file, Some(*line))
+            ),
+            CompileError::UnknownMethod { name, file, line } => write!(
+                f,
+                "{}method `{}` is not defined in the `fr` namespace",
+                location(file, *line),
+                name
+            ),
+            CompileError::InvalidOsts { file, line, column, message } => write!(
+                f,
+                "{}invalid .osts file (column {}): {}",
+                location(file, Some(*line)),
+                column,
+                message
+            ),
+            CompileError::UnresolvedReference { name, file, line } => write!(
+                f,
+                "{}line {} of the compiled output uses `{}`, which was removed from the `fr` namespace",
+                location(file, None),
+                line,
+                name
+            ),
+            CompileError::InvalidConfig { file, message } => {
+                write!(f, "{}: invalid config: {}", file.display(), message)
+            }
+            CompileError::Io { file, source } => {
+                write!(f, "{}: {}", file.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CompileError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_file_fills_missing_location() {
+        let err = CompileError::MissingConstructor { file: None, line: 3 }.with_file(Path::new("a.osts"));
+        assert_eq!(err.to_string(), "a.osts:3: the `fr` namespace has no DataFrame constructor");
+    }
+
+    #[test]
+    fn with_file_keeps_existing_location() {
+        let err = CompileError::MissingNamespace { file: Some(PathBuf::from("first.osts")) }
+            .with_file(Path::new("second.osts"));
+        assert!(err.to_string().starts_with("first.osts: "));
+    }
+
+    #[test]
+    fn unresolved_references_name_the_compiled_line() {
+        let err = CompileError::UnresolvedReference { name: "fr.read_csv".to_string(), file: None, line: 42 }
+            .with_file(Path::new("a.osts"));
+        assert_eq!(
+            err.to_string(),
+            "a.osts: line 42 of the compiled output uses `fr.read_csv`, which was removed from the `fr` namespace"
+        );
+    }
+
+    #[test]
+    fn io_errors_expose_their_source() {
+        let err = CompileError::Io {
+            file: PathBuf::from("missing.osts"),
+            source: io::Error::new(io::ErrorKind::NotFound, "not found"),
+        };
+        assert!(err.is_io());
+        assert!(std::error::Error::source(&err).is_some());
+    }
+}
@@ -0,0 +1,186 @@
+//! # explain
+//!
+//! Records why each symbol was kept by compilation: the root it was reached from,
+//! like a call in `main`, and the chain of calls through the `fr` namespace from there.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use indexmap::IndexMap;
+use super::graph::utils::Vertex;
+use super::lexer::{code_tokens, TokenKind};
+
+/// Where a chain of kept symbols starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// Used by the code outside the `fr` namespace. `line` is the 1-based line of the
+    /// first use in the script body and `code` the use itself, like `df.describe()`,
+    /// when it could be found
+    Main { line: Option<usize>, code: String },
+    /// Listed in a `permafrost-keep` directive or the `keep` list of `permafrost.toml`
+    KeepList,
+    /// Referenced by code in the namespace that's always kept, like class fields
+    Namespace,
+    /// Kept regardless of usage, like the DataFrame constructor
+    AlwaysKept,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Main { line: Some(line), code } => write!(f, "main:line {} {}", line, code),
+            Origin::Main { line: None, .. } => write!(f, "main"),
+            Origin::KeepList => write!(f, "keep list"),
+            Origin::Namespace => write!(f, "fr namespace"),
+            Origin::AlwaysKept => write!(f, "always kept"),
+        }
+    }
+}
+
+/// Why a symbol was kept: where its chain starts and every symbol along the way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub origin: Origin,
+    /// The symbols from the root to the explained one, both included, like
+    /// `["fr::DataFrame::describe", "fr::getNumericColumns"]`
+    pub chain: Vec<Vertex>,
+}
+
+/// Prints like `main:line 12 df.describe() -> describe -> fr.getNumericColumns`.
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.origin)?;
+        for vertex in &self.chain {
+            write!(f, " -> {}", short_name(vertex))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the name a symbol is written with: `describe` for `fr::DataFrame::describe`,
+/// `fr.predicates.equal` for `fr::predicates::equal` and `fr.read_csv` for `fr::read_csv`.
+/// Namespace items keep their `fr.`, since many share a name with a DataFrame method.
+pub fn short_name(vertex: &str) -> String {
+    match vertex.strip_prefix("fr::DataFrame::") {
+        Some(method) => method.to_string(),
+        None => vertex.replace("::", "."),
+    }
+}
+
+/// Builds the explanation of every vertex in a search tree from `Graph::search_tree`,
+/// given the origin of each root.
+pub fn explain(tree: &IndexMap<Vertex, Option<Vertex>>, origins: &IndexMap<Vertex, Origin>) -> BTreeMap<Vertex, Explanation> {
+    tree.keys()
+        .map(|vertex| {
+            let mut chain = vec![vertex.clone()];
+            while let Some(Some(parent)) = tree.get(chain.last().unwrap_or(vertex)) {
+                chain.push(parent.clone());
+            }
+            chain.reverse();
+            let origin = origins.get(&chain[0]).cloned().unwrap_or(Origin::AlwaysKept);
+            (vertex.clone(), Explanation { origin, chain })
+        })
+        .collect()
+}
+
+/// Finds the first use of `vertex` in `main`, returning the line it's on and the use as
+/// written, like `(12, "df.describe()")`.
+///
+/// `site` is where the analyzer recorded the first call, as a byte offset in `parsed`: main
+/// with its comments stripped, which leaves the code tokens unchanged. Without a site, as
+/// for members found by name, the first `.name` in main is taken. `line_offset` is added
+/// to lines after `offset_from`, for code that was removed from between them like the `fr`
+/// namespace.
+pub fn locate(
+    main: &str,
+    parsed: &str,
+    site: Option<usize>,
+    vertex: &str,
+    offset_from: usize,
+    line_offset: usize,
+) -> Option<(usize, String)> {
+    let tokens = code_tokens(main);
+    let i = match site {
+        Some(offset) => code_tokens(parsed).iter().position(|t| t.start == offset)?,
+        None => {
+            let mut parts = vertex.rsplit("::");
+            let mut name = parts.next()?;
+            // `new fr.DataFrame(...)` is what calls the constructor
+            if name == "constructor" {
+                name = parts.next()?;
+            }
+            (1..tokens.len()).find(|&i| {
+                tokens[i].kind == TokenKind::Ident
+                    && tokens[i].is(name)
+                    && (tokens[i - 1].is(".") || tokens[i - 1].is("?."))
+            })?
+        }
+    };
+
+    // Members like `df["head"]()` are written as `df.head()`
+    let (name, next) = match tokens.get(i)? {
+        tok if tok.kind == TokenKind::String => (&tok.text[1..tok.text.len() - 1], i + 2),
+        tok => (tok.text, i + 1),
+    };
+    let object = tokens.get(i.wrapping_sub(2)).filter(|t| t.kind == TokenKind::Ident);
+    let call = if tokens.get(next).is_some_and(|t| t.is("(")) { "()" } else { "" };
+    let code = match object {
+        Some(object) => format!("{}.{}{}", object.text, name, call),
+        None => format!("{}{}", name, call),
+    };
+
+    let line = tokens[i].line;
+    let line = if line >= offset_from { line + line_offset } else { line };
+    Some((line, code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explanations_follow_parents_back_to_their_root() {
+        let tree = IndexMap::from([
+            ("fr::DataFrame::describe".to_string(), None),
+            ("fr::getNumericColumns".to_string(), Some("fr::DataFrame::describe".to_string())),
+            ("fr::DataFrame::__check_numeric".to_string(), Some("fr::getNumericColumns".to_string())),
+        ]);
+        let origins = IndexMap::from([(
+            "fr::DataFrame::describe".to_string(),
+            Origin::Main { line: Some(12), code: "df.describe()".to_string() },
+        )]);
+
+        let explanations = explain(&tree, &origins);
+        assert_eq!(
+            explanations["fr::DataFrame::__check_numeric"].to_string(),
+            "main:line 12 df.describe() -> describe -> fr.getNumericColumns -> __check_numeric"
+        );
+        assert_eq!(explanations["fr::DataFrame::describe"].chain, ["fr::DataFrame::describe"]);
+    }
+
+    #[test]
+    fn locates_the_first_use_in_main() {
+        let main = "function main() {\n    // df.describe() in a comment\n    let df = new fr.DataFrame(data);\n    df.describe();\n}";
+
+        assert_eq!(locate(main, main, None, "fr::DataFrame::describe", 1, 0), Some((4, "df.describe()".to_string())));
+        assert_eq!(locate(main, main, None, "fr::DataFrame::constructor", 1, 0), Some((3, "fr.DataFrame()".to_string())));
+        assert_eq!(locate(main, main, None, "fr::DataFrame::describe", 2, 10), Some((14, "df.describe()".to_string())));
+        assert_eq!(locate(main, main, None, "fr::FrostRow", 1, 0), None);
+    }
+
+    #[test]
+    fn locates_the_call_site_the_analyzer_recorded() {
+        let main = "function main() {\n    // a comment\n    [1].filter(x => x);\n    df[\"filter\"](\"Age\");\n}";
+        let parsed = "function main() {\n    [1].filter(x => x);\n    df[\"filter\"](\"Age\");\n}";
+        let site = parsed.find("\"filter\"");
+
+        assert_eq!(locate(main, parsed, site, "fr::DataFrame::filter", 1, 0), Some((4, "df.filter()".to_string())));
+        assert_eq!(locate(main, parsed, None, "fr::DataFrame::filter", 1, 0), Some((3, "filter()".to_string())));
+    }
+
+    #[test]
+    fn short_names_drop_the_namespace() {
+        assert_eq!(short_name("fr::DataFrame::merge"), "merge");
+        assert_eq!(short_name("fr::predicates::equal"), "fr.predicates.equal");
+        assert_eq!(short_name("fr::read_csv"), "fr.read_csv");
+    }
+}
@@ -0,0 +1,321 @@
+//! # analysis
+//!
+//! Structural analysis of the `fr` call graph for library maintainers: cycles, dead
+//! helpers, fan-in and fan-out, and how much code each public method drags in.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use super::utils::{method_key, Vertex};
+use super::Graph;
+use crate::compile::explain::short_name;
+use crate::compile::source::FrostFunctionSet;
+
+/// How many symbols call a symbol, and how many it calls. Self-calls aren't counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fan {
+    pub symbol: Vertex,
+    pub fan_in: usize,
+    pub fan_out: usize,
+}
+
+/// The result of `analyze`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryReport {
+    /// Number of symbols in the graph
+    pub symbols: usize,
+    /// Strongly connected components that contain a cycle, including self-recursive
+    /// symbols, each in source order. Interfaces and type aliases are left out.
+    pub cycles: Vec<Vec<Vertex>>,
+    /// Symbols no public symbol can reach, like dead private helpers
+    pub unreachable: Vec<Vertex>,
+    /// Fan-in and fan-out of every symbol but interfaces and type aliases, most called first
+    pub fan: Vec<Fan>,
+    /// Bytes each public symbol adds to a compiled script on top of what's always kept,
+    /// most expensive first
+    pub costs: Vec<(Vertex, usize)>,
+}
+
+impl Graph {
+    /// Returns the strongly connected components of the graph, found with Tarjan's
+    /// algorithm. Every vertex is in exactly one component, and components come out
+    /// callees first.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<Vertex>> {
+        struct Tarjan<'a> {
+            graph: &'a Graph,
+            next_index: usize,
+            index: HashMap<&'a str, usize>,
+            low_link: HashMap<&'a str, usize>,
+            stack: Vec<&'a str>,
+            on_stack: HashSet<&'a str>,
+            components: Vec<Vec<Vertex>>,
+        }
+
+        impl<'a> Tarjan<'a> {
+            fn visit(&mut self, vertex: &'a str) {
+                self.index.insert(vertex, self.next_index);
+                self.low_link.insert(vertex, self.next_index);
+                self.next_index += 1;
+                self.stack.push(vertex);
+                self.on_stack.insert(vertex);
+
+                for callee in self.graph.adj_list.get(vertex).into_iter().flatten() {
+                    if !self.index.contains_key(callee.as_str()) {
+                        self.visit(callee);
+                        let low = self.low_link[vertex].min(self.low_link[callee.as_str()]);
+                        self.low_link.insert(vertex, low);
+                    } else if self.on_stack.contains(callee.as_str()) {
+                        let low = self.low_link[vertex].min(self.index[callee.as_str()]);
+                        self.low_link.insert(vertex, low);
+                    }
+                }
+
+                if self.low_link[vertex] == self.index[vertex] {
+                    let mut component = Vec::new();
+                    while let Some(member) = self.stack.pop() {
+                        self.on_stack.remove(member);
+                        component.push(member.to_string());
+                        if member == vertex {
+                            break;
+                        }
+                    }
+                    self.components.push(component);
+                }
+            }
+        }
+
+        let mut tarjan = Tarjan {
+            graph: self,
+            next_index: 0,
+            index: HashMap::new(),
+            low_link: HashMap::new(),
+            stack: Vec::new(),
+            on_stack: HashSet::new(),
+            components: Vec::new(),
+        };
+        for vertex in self.adj_list.keys() {
+            if !tarjan.index.contains_key(vertex.as_str()) {
+                tarjan.visit(vertex);
+            }
+        }
+        tarjan.components
+    }
+
+    /// Returns the components of `strongly_connected_components` that contain a cycle:
+    /// those with several symbols, and symbols that call themselves.
+    pub fn cycles(&self) -> Vec<Vec<Vertex>> {
+        self.strongly_connected_components()
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1 || self.adj_list.get(&component[0]).is_some_and(|callees| callees.contains(&component[0]))
+            })
+            .map(|mut component| {
+                component.sort_by_key(|vertex| self.adj_list.get_index_of(vertex));
+                component
+            })
+            .collect()
+    }
+}
+
+/// Returns true for the edges between an item and its own members, like from
+/// `fr::DataFrame::head` to `fr::DataFrame` or from `fr::DataFrame` to its constructor,
+/// which say where code is emitted rather than what it calls.
+fn is_containment(vertex: &str, callee: &str) -> bool {
+    let contains = |outer: &str, inner: &str| inner.strip_prefix(outer).is_some_and(|rest| rest.starts_with("::"));
+    contains(vertex, callee) || contains(callee, vertex)
+}
+
+/// Analyzes the call graph of a frosts library. Public symbols are the entry points that
+/// scripts can use, see `FrostFunctionSet::is_public`.
+pub fn analyze(graph: &Graph, funct_set: &FrostFunctionSet) -> LibraryReport {
+    let sizes: HashMap<String, usize> = funct_set
+        .symbols()
+        .into_iter()
+        .map(|(vertex, code)| (vertex, code.len()))
+        .collect();
+    let public: Vec<Vertex> = graph.adj_list.keys().filter(|v| funct_set.is_public(v)).cloned().collect();
+
+    // 1️⃣ Anything public symbols and the always kept constructor can't reach is dead
+    let mut entry_points = public.clone();
+    entry_points.push(method_key("constructor"));
+    let reachable = graph.search(&entry_points);
+    let unreachable = graph.adj_list.keys().filter(|v| !reachable.contains(*v)).cloned().collect();
+
+    // 2️⃣ Cycles, fan-in and fan-out only count real calls, not where members are emitted
+    // or the types they're declared with
+    let calls = Graph {
+        n: graph.n,
+        adj_list: graph
+            .adj_list
+            .iter()
+            .filter(|(vertex, _)| !funct_set.is_type(vertex))
+            .map(|(vertex, callees)| {
+                let callees = callees
+                    .iter()
+                    .filter(|callee| !is_containment(vertex, callee) && !funct_set.is_type(callee))
+                    .cloned()
+                    .collect();
+                (vertex.clone(), callees)
+            })
+            .collect(),
+        gates: HashMap::new(),
+        private: graph.private.clone(),
+    };
+    let mut fan_in: HashMap<&str, usize> = HashMap::new();
+    for (vertex, callees) in &calls.adj_list {
+        for callee in callees.iter().filter(|callee| *callee != vertex) {
+            *fan_in.entry(callee).or_default() += 1;
+        }
+    }
+    let mut fan: Vec<Fan> = calls
+        .adj_list
+        .iter()
+        .map(|(vertex, callees)| Fan {
+            symbol: vertex.clone(),
+            fan_in: fan_in.get(vertex.as_str()).copied().unwrap_or_default(),
+            fan_out: callees.iter().filter(|callee| *callee != vertex).count(),
+        })
+        .collect();
+    fan.sort_by(|a, b| b.fan_in.cmp(&a.fan_in).then(b.fan_out.cmp(&a.fan_out)));
+
+    // 3️⃣ The cost of a public symbol is everything it reaches that isn't always kept
+    let always_kept = graph.search(&[method_key("constructor")]);
+    let mut costs: Vec<(Vertex, usize)> = public
+        .iter()
+        .filter(|vertex| !always_kept.contains(*vertex))
+        .map(|vertex| {
+            let cost = graph
+                .search(std::slice::from_ref(vertex))
+                .iter()
+                .filter(|reached| !always_kept.contains(*reached))
+                .filter_map(|reached| sizes.get(reached))
+                .sum();
+            (vertex.clone(), cost)
+        })
+        .collect();
+    costs.sort_by_key(|(_, cost)| std::cmp::Reverse(*cost));
+
+    LibraryReport {
+        symbols: graph.adj_list.len(),
+        cycles: calls.cycles(),
+        unreachable,
+        fan,
+        costs,
+    }
+}
+
+impl fmt::Display for LibraryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "❄️ {} symbols in the fr namespace", self.symbols)?;
+
+        writeln!(f, "\nCycles ({})", self.cycles.len())?;
+        for cycle in &self.cycles {
+            let names: Vec<String> = cycle.iter().map(|v| short_name(v)).collect();
+            writeln!(f, "  {}", names.join(" <-> "))?;
+        }
+
+        writeln!(f, "\nUnreachable from public symbols ({})", self.unreachable.len())?;
+        for vertex in &self.unreachable {
+            writeln!(f, "  {}", short_name(vertex))?;
+        }
+
+        writeln!(f, "\nFan-in / fan-out")?;
+        for fan in &self.fan {
+            writeln!(f, "  {:>3} {:>3}  {}", fan.fan_in, fan.fan_out, short_name(&fan.symbol))?;
+        }
+
+        writeln!(f, "\nTransitive cost of public symbols, in bytes beyond what's always kept")?;
+        for (vertex, cost) in &self.costs {
+            writeln!(f, "  {:>7}  {}", cost, short_name(vertex))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn graph(edges: &[(&str, &[&str])]) -> Graph {
+        let adj_list = edges
+            .iter()
+            .map(|(vertex, callees)| (vertex.to_string(), callees.iter().map(|c| c.to_string()).collect()))
+            .collect();
+        Graph { n: edges.len(), adj_list, gates: HashMap::new(), private: HashSet::new() }
+    }
+
+    #[test]
+    fn finds_cycles_and_self_recursion() {
+        let g = graph(&[
+            ("a", &["b"]),
+            ("b", &["c"]),
+            ("c", &["a", "d"]),
+            ("d", &[]),
+            ("e", &["e"]),
+        ]);
+
+        assert_eq!(g.strongly_connected_components().len(), 3);
+        assert_eq!(g.cycles(), vec![vec!["a", "b", "c"], vec!["e"]]);
+    }
+
+    #[test]
+    fn containment_edges_link_items_and_their_own_members() {
+        assert!(is_containment("fr::DataFrame::head", "fr::DataFrame"));
+        assert!(is_containment("fr::DataFrame", "fr::DataFrame::constructor"));
+        assert!(!is_containment("fr::DataFrame::head", "fr::DataFrame::tail"));
+        assert!(!is_containment("fr::Data", "fr::DataFrame::head"));
+    }
+
+    #[test]
+    fn reports_dead_helpers_fan_and_costs() {
+        let funct_set = FrostFunctionSet {
+            always_take: String::new(),
+            namespace_items: IndexMap::from([("helper".to_string(), "function helper() {}".to_string())]),
+            object_members: IndexMap::new(),
+            class_members: IndexMap::new(),
+            dataframe_methods: IndexMap::from([
+                ("constructor".to_string(), "constructor() {}".to_string()),
+                ("describe".to_string(), "describe() { this.__numeric(); helper(); }".to_string()),
+                ("__numeric".to_string(), "private __numeric() {}".to_string()),
+                ("__dead".to_string(), "__dead() {}".to_string()),
+            ]),
+            spans: HashMap::new(),
+        };
+        let g = Graph::from_function_set(&funct_set);
+        let report = analyze(&g, &funct_set);
+
+        assert_eq!(report.unreachable, ["fr::DataFrame::__dead"]);
+        assert!(!funct_set.is_public("fr::helper"));
+        assert_eq!(report.costs, [("fr::DataFrame::describe".to_string(), 42 + 22 + 20)]);
+
+        let helper = report.fan.iter().find(|fan| fan.symbol == "fr::helper").unwrap();
+        assert_eq!((helper.fan_in, helper.fan_out), (1, 0));
+        assert_eq!(report.fan[0].symbol, "fr::helper");
+    }
+
+    #[test]
+    fn types_are_left_out_of_cycles_and_fan() {
+        let funct_set = FrostFunctionSet {
+            always_take: String::new(),
+            namespace_items: IndexMap::from([
+                ("CellValue".to_string(), "export type CellValue = string | number;".to_string()),
+                ("Row".to_string(), "export type Row = { [key: string]: CellValue };".to_string()),
+                ("Json".to_string(), "export type Json = CellValue | JsonObject;".to_string()),
+                ("JsonObject".to_string(), "export interface JsonObject { [key: string]: Json }".to_string()),
+                ("first".to_string(), "export function first(rows: Row[]): CellValue { return rows[0]; }".to_string()),
+            ]),
+            object_members: IndexMap::new(),
+            class_members: IndexMap::new(),
+            dataframe_methods: IndexMap::new(),
+            spans: HashMap::new(),
+        };
+        let g = Graph::from_function_set(&funct_set);
+        let report = analyze(&g, &funct_set);
+
+        assert!(funct_set.is_type("fr::Row") && funct_set.is_type("fr::JsonObject"));
+        assert!(!funct_set.is_type("fr::first"));
+        assert_eq!(g.cycles(), [["fr::Json", "fr::JsonObject"]]);
+        assert!(report.cycles.is_empty());
+        assert_eq!(report.fan, [Fan { symbol: "fr::first".to_string(), fan_in: 0, fan_out: 0 }]);
+    }
+}
@@ -0,0 +1,255 @@
+//! # export
+//!
+//! Renders a call graph for humans and tools: Graphviz DOT, Mermaid flowcharts or JSON.
+//!
+//! Vertices reachable from the script's `main` are highlighted, and private helpers, the
+//! same ones `analyze-lib` treats as non-public, are drawn dashed.
+
+use std::collections::HashSet;
+use serde::Serialize;
+use super::Graph;
+use crate::compile::explain::short_name;
+
+/// Output formats for `Graph::render`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum GraphFormat {
+    /// Graphviz, rendered with `dot -Tsvg`
+    #[default]
+    Dot,
+    /// A Mermaid flowchart, which renders inline in Markdown
+    Mermaid,
+    /// Nodes and edges as JSON
+    Json,
+}
+
+#[derive(Serialize)]
+struct JsonNode<'a> {
+    id: &'a str,
+    label: String,
+    reachable: bool,
+    private: bool,
+}
+
+#[derive(Serialize)]
+struct JsonEdge<'a> {
+    from: &'a str,
+    to: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonGraph<'a> {
+    nodes: Vec<JsonNode<'a>>,
+    edges: Vec<JsonEdge<'a>>,
+}
+
+impl Graph {
+    /// Renders every vertex and edge in `format`, highlighting the `reachable` vertices.
+    pub fn render(&self, format: GraphFormat, reachable: &HashSet<String>) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(reachable),
+            GraphFormat::Mermaid => self.to_mermaid(reachable),
+            GraphFormat::Json => self.to_json(reachable),
+        }
+    }
+
+    /// Renders the graph as a Graphviz digraph.
+    pub fn to_dot(&self, reachable: &HashSet<String>) -> String {
+        let mut out = String::from("digraph fr {\n    rankdir=LR;\n    node [shape=box, fontname=\"Helvetica\"];\n");
+        for vertex in self.adj_list.keys() {
+            let mut style = Vec::new();
+            if reachable.contains(vertex) {
+                style.push("filled");
+            }
+            if self.private.contains(vertex) {
+                style.push("dashed");
+            }
+            out.push_str(&format!("    \"{}\" [label=\"{}\"", escape_dot(vertex), escape_dot(&short_name(vertex))));
+            if !style.is_empty() {
+                out.push_str(&format!(", style=\"{}\"", style.join(",")));
+            }
+            if reachable.contains(vertex) {
+                out.push_str(", fillcolor=\"#cfe8ff\"");
+            }
+            out.push_str("];\n");
+        }
+        for (vertex, callees) in &self.adj_list {
+            for callee in callees {
+                out.push_str(&format!("    \"{}\" -> \"{}\";\n", escape_dot(vertex), escape_dot(callee)));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Renders the graph as a Mermaid flowchart. Nodes are numbered, since Mermaid ids
+    /// can't contain `::` or the `<>` of generic methods.
+    pub fn to_mermaid(&self, reachable: &HashSet<String>) -> String {
+        let mut out = String::from("flowchart LR\n");
+        for (i, vertex) in self.adj_list.keys().enumerate() {
+            out.push_str(&format!("    n{}[\"{}\"]\n", i, escape_mermaid(&short_name(vertex))));
+        }
+        for (from, callees) in self.adj_list.values().enumerate() {
+            for callee in callees.iter().filter_map(|callee| self.adj_list.get_index_of(callee)) {
+                out.push_str(&format!("    n{} --> n{}\n", from, callee));
+            }
+        }
+
+        out.push_str("    classDef reachable fill:#cfe8ff,stroke:#1f6feb\n");
+        out.push_str("    classDef private stroke-dasharray:5 5\n");
+        let reachable_ids = self.mermaid_ids(|vertex| reachable.contains(vertex));
+        if !reachable_ids.is_empty() {
+            out.push_str(&format!("    class {} reachable\n", reachable_ids.join(",")));
+        }
+        let private_ids = self.mermaid_ids(|vertex| self.private.contains(vertex));
+        if !private_ids.is_empty() {
+            out.push_str(&format!("    class {} private\n", private_ids.join(",")));
+        }
+        out
+    }
+
+    /// Returns the Mermaid ids of the vertices that `include` accepts.
+    fn mermaid_ids(&self, include: impl Fn(&str) -> bool) -> Vec<String> {
+        self.adj_list
+            .keys()
+            .enumerate()
+            .filter(|(_, vertex)| include(vertex))
+            .map(|(i, _)| format!("n{}", i))
+            .collect()
+    }
+
+    /// Renders the graph as JSON, like
+    /// `{"nodes": [{"id": "fr::DataFrame::head", "label": "head", ...}], "edges": [...]}`.
+    pub fn to_json(&self, reachable: &HashSet<String>) -> String {
+        let graph = JsonGraph {
+            nodes: self
+                .adj_list
+                .keys()
+                .map(|vertex| JsonNode {
+                    id: vertex,
+                    label: short_name(vertex),
+                    reachable: reachable.contains(vertex),
+                    private: self.private.contains(vertex),
+                })
+                .collect(),
+            edges: self
+                .adj_list
+                .iter()
+                .flat_map(|(from, callees)| callees.iter().map(move |to| JsonEdge { from, to }))
+                .collect(),
+        };
+        serde_json::to_string_pretty(&graph).unwrap_or_default()
+    }
+}
+
+/// Escapes text for a double-quoted Graphviz string.
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes text for a quoted Mermaid label, where `"` ends the label and `<>` would be
+/// read as HTML.
+fn escape_mermaid(text: &str) -> String {
+    text.replace('"', "#quot;").replace('<', "#lt;").replace('>', "#gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+    use std::collections::HashMap;
+
+    fn sample() -> (Graph, HashSet<String>) {
+        let adj_list = IndexMap::from([
+            ("fr::DataFrame::head".to_string(), vec!["fr::DataFrame::__copy".to_string()]),
+            ("fr::DataFrame::__copy".to_string(), vec![]),
+            ("fr::read_csv".to_string(), vec![]),
+        ]);
+        let private = HashSet::from(["fr::DataFrame::__copy".to_string()]);
+        let graph = Graph { n: 3, adj_list, gates: HashMap::new(), private };
+        let reachable = HashSet::from(["fr::DataFrame::head".to_string(), "fr::DataFrame::__copy".to_string()]);
+        (graph, reachable)
+    }
+
+    #[test]
+    fn dot_highlights_reachable_and_private_nodes() {
+        let (graph, reachable) = sample();
+        let dot = graph.to_dot(&reachable);
+
+        assert!(dot.starts_with("digraph fr {"));
+        assert!(dot.contains("\"fr::DataFrame::head\" [label=\"head\", style=\"filled\", fillcolor=\"#cfe8ff\"];"));
+        assert!(dot.contains("\"fr::DataFrame::__copy\" [label=\"__copy\", style=\"filled,dashed\""));
+        assert!(dot.contains("\"fr::read_csv\" [label=\"fr.read_csv\"];"));
+        assert!(dot.contains("\"fr::DataFrame::head\" -> \"fr::DataFrame::__copy\";"));
+    }
+
+    #[test]
+    fn mermaid_numbers_nodes_and_assigns_classes() {
+        let (graph, reachable) = sample();
+        let mermaid = graph.to_mermaid(&reachable);
+
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains("    n0[\"head\"]\n"));
+        assert!(mermaid.contains("    n0 --> n1\n"));
+        assert!(mermaid.contains("    class n0,n1 reachable\n"));
+        assert!(mermaid.contains("    class n1 private\n"));
+    }
+
+    #[test]
+    fn labels_and_ids_are_escaped_for_each_format() {
+        let adj_list = IndexMap::from([
+            ("fr::DataFrame::__apply_typed<T>".to_string(), vec!["fr::DataFrame::\"quoted\"".to_string()]),
+            ("fr::DataFrame::\"quoted\"".to_string(), vec![]),
+        ]);
+        let graph = Graph { n: 2, adj_list, gates: HashMap::new(), private: HashSet::new() };
+        let dot = graph.to_dot(&HashSet::new());
+        let mermaid = graph.to_mermaid(&HashSet::new());
+
+        assert!(dot.contains("\"fr::DataFrame::__apply_typed<T>\" [label=\"__apply_typed<T>\"];"));
+        assert!(dot.contains("\"fr::DataFrame::\\\"quoted\\\"\" [label=\"\\\"quoted\\\"\"];"));
+        assert!(dot.contains("\"fr::DataFrame::__apply_typed<T>\" -> \"fr::DataFrame::\\\"quoted\\\"\";"));
+        assert!(mermaid.contains("    n0[\"__apply_typed#lt;T#gt;\"]\n"));
+        assert!(mermaid.contains("    n1[\"#quot;quoted#quot;\"]\n"));
+    }
+
+    #[test]
+    fn json_lists_nodes_and_edges() {
+        let (graph, reachable) = sample();
+        let json: serde_json::Value = serde_json::from_str(&graph.to_json(&reachable)).unwrap();
+
+        assert_eq!(json["nodes"][1]["id"], "fr::DataFrame::__copy");
+        assert_eq!(json["nodes"][1]["private"], true);
+        assert_eq!(json["nodes"][2]["reachable"], false);
+        assert_eq!(json["edges"][0]["to"], "fr::DataFrame::__copy");
+    }
+
+    #[test]
+    fn private_vertices_match_the_visibility_of_their_declarations() {
+        let script = "namespace fr {
+    export function read_csv(text: string): DataFrame {
+        return new DataFrame();
+    }
+    function parse(text: string): string[][] {
+        return [[text]];
+    }
+    export class DataFrame {
+        constructor() {}
+        head(): DataFrame {
+            return this.__copy();
+        }
+        private __copy(): DataFrame {
+            return this.trim();
+        }
+        protected trim(): DataFrame {
+            return this;
+        }
+    }
+}
+function main() {}";
+        let graph = crate::compile::call_graph(script, &Default::default()).unwrap();
+        let mut private: Vec<&str> = graph.private.iter().map(String::as_str).collect();
+        private.sort();
+
+        assert_eq!(private, ["fr::DataFrame::__copy", "fr::DataFrame::trim", "fr::parse"]);
+    }
+}
@@ -1,34 +1,83 @@
 pub mod utils; 
 
+/// Finds cycles, dead helpers and the cost of each method for library maintainers.
+pub mod analysis;
+
+/// Renders the graph as DOT, Mermaid or JSON.
+pub mod export;
+
 use std::collections::{HashMap,HashSet,VecDeque};
+use indexmap::IndexMap;
 use utils::*;
-use super::source::FrostFunctionSet;
-use super::code_parser::FunctionParser;
+use super::source::{FrostFunctionSet, DATAFRAME};
+use super::code_parser::{bare_identifiers, ParserBackend, UsageAnalyzer, NAMESPACE};
+use super::lexer::{code_tokens, TokenKind};
 
+#[derive(Debug, Clone)]
 pub struct Graph{
     n: usize,
-    pub adj_list: AdjList
+    pub adj_list: AdjList,
+    /// Vertices that are only reachable once another vertex is, like the methods of a
+    /// class other than DataFrame, which can't be used until the class itself is
+    pub gates: HashMap<Vertex, Vertex>,
+    /// Vertices scripts can't use directly, see `FrostFunctionSet::is_public`
+    pub private: HashSet<Vertex>,
 }
 impl Graph{
     pub fn from_function_set(funct_set: &FrostFunctionSet) -> Self {
-        let mut adj_list: AdjList = HashMap::new();
-        let mut n = 0;
+        Self::from_function_set_with(funct_set, ParserBackend::default())
+    }
 
-        funct_set
-            .dataframe_methods
-            .iter()
-            .for_each(|(orig_method, code)|{
-                //Parse the code for the method
-                let mut parser = FunctionParser::new();
-                parser.parse(code,"this");
-                
-                n += 1;
-                adj_list.insert(strip_generics(orig_method), parser.get_methods());
-            });
-        
-        let mut g = Graph{n, adj_list};
+    /// Builds the call graph, analyzing each method with the given parser backend.
+    ///
+    /// Vertices are namespace-qualified symbols: top-level items like `fr::detectColumn`,
+    /// types like `fr::Row`, members of object literals like `fr::predicates::is_blank`,
+    /// methods of other classes like `fr::Series::sum` and DataFrame methods like
+    /// `fr::DataFrame::merge`. Edges come from `this.x()`,
+    /// `fr.x` and bare `x(...)` references, from methods called on DataFrames created
+    /// inside helpers, and from interfaces like `FrostRow` used by callbacks.
+    pub fn from_function_set_with(funct_set: &FrostFunctionSet, backend: ParserBackend) -> Self {
+        let mut adj_list = references(funct_set, funct_set, backend);
+        let n = adj_list.len();
+
+        // A member is emitted inside its object literal or class, so it needs that too
+        for (object, members) in &funct_set.object_members {
+            for member in members.keys() {
+                if let Some(callees) = adj_list.get_mut(&member_key(object, member)) {
+                    callees.push(namespace_key(object));
+                }
+            }
+        }
+        for name in funct_set.dataframe_methods.keys() {
+            if let Some(callees) = adj_list.get_mut(&method_key(name)) {
+                callees.push(namespace_key(DATAFRAME));
+            }
+        }
+
+        // Constructing a class runs its constructor
+        if let Some(callees) = adj_list.get_mut(&namespace_key(DATAFRAME)) {
+            callees.push(method_key("constructor"));
+        }
+        let mut gates = HashMap::new();
+        for (class, members) in &funct_set.class_members {
+            if let Some(callees) = adj_list.get_mut(&namespace_key(class)) {
+                callees.extend(members.contains_key("constructor").then(|| member_key(class, "constructor")));
+            }
+            // Methods of other classes are matched by name alone, so they're only kept
+            // once their class is
+            gates.extend(members.keys().map(|member| (member_key(class, member), namespace_key(class))));
+        }
+
+        let private = adj_list.keys().filter(|v| !funct_set.is_public(v)).cloned().collect();
+        let mut g = Graph{n, adj_list, gates, private};
         g.clean_edges();
-        return g;
+        g
+    }
+
+    /// Removes `vertices` and every edge into them, so nothing is reached through them.
+    pub fn remove_vertices(&mut self, vertices: &HashSet<Vertex>) {
+        self.adj_list.retain(|vertex, _| !vertices.contains(vertex));
+        self.clean_edges();
     }
 
     // A function that cleans the edges of the call graph to drop any edges that aren't locally defined
@@ -57,38 +106,42 @@ impl Graph{
 
     
 
-    pub fn search(&self, roots: &Vec<String>) -> HashSet<String> {
-        let mut visited = HashSet::new();
-        let mut queue: VecDeque<String> = VecDeque::new();
-
-        println!("Beginning frostbyte, gathering necessary functions...");
-        for root in roots {
-            if self.adj_list.contains_key(root){
-                visited.insert(root.clone());
-                queue.push_back(root.clone());
-            } 
-            //Special case, the combine_dfs function requires some method processing
-            else if root == "combine_dfs"{
-                visited.insert("concat_all".to_string());
-                queue.push_back("concat_all".to_string());
+    /// Returns every vertex reachable from `roots`. A gated vertex is parked until its
+    /// gate is reached, and dropped if it never is.
+    pub fn search(&self, roots: &[String]) -> HashSet<String> {
+        self.search_tree(roots).into_keys().collect()
+    }
+
+    /// Searches like `search`, but maps each reachable vertex to the vertex it was first
+    /// reached from, or `None` for roots. Following the parents back gives the shortest
+    /// chain of calls from a root, and vertices are in the order they were reached.
+    pub fn search_tree(&self, roots: &[String]) -> IndexMap<Vertex, Option<Vertex>> {
+        let mut visited: IndexMap<Vertex, Option<Vertex>> = IndexMap::new();
+        let mut parked: HashMap<&str, Vec<(&str, Option<&str>)>> = HashMap::new();
+        let mut queue: VecDeque<(&str, Option<&str>)> = roots
+            .iter()
+            .filter(|root| self.adj_list.contains_key(*root))
+            .map(|root| (root.as_str(), None))
+            .collect();
+
+        while let Some((current, parent)) = queue.pop_front() {
+            if visited.contains_key(current) {
+                continue;
+            }
+            if let Some(gate) = self.gates.get(current).filter(|gate| !visited.contains_key(*gate)) {
+                parked.entry(gate.as_str()).or_default().push((current, parent));
+                continue;
             }
-        }
 
-        while let Some(current) = queue.pop_front() {
-            if let Some(callees) = self.adj_list.get(&current) {
-                for callee in callees {
-                    if visited.insert(callee.clone()) {
-                        queue.push_back(callee.clone());
-                    }
-                }
+            visited.insert(current.to_string(), parent.map(String::from));
+            queue.extend(parked.remove(current).unwrap_or_default());
+            if let Some(callees) = self.adj_list.get(current) {
+                queue.extend(callees.iter().map(|callee| (callee.as_str(), Some(current))));
             }
         }
-        
-        println!("");
         visited
-        
     }
-    
+
     //Print method used for debugging
     #[allow(dead_code)]
     pub fn print(&self){
@@ -96,11 +149,158 @@ impl Graph{
     }
 }
 
+/// Returns what each symbol of `funct_set` references, resolving names against the
+/// symbols of `library`. Compiled output is checked by resolving its reduced set against
+/// the full library, so references to removed symbols still show up.
+pub fn references(funct_set: &FrostFunctionSet, library: &FrostFunctionSet, backend: ParserBackend) -> AdjList {
+    let mut adj_list: AdjList = IndexMap::new();
+    let callbacks = library.callback_types();
+
+    // Classes and object literals only keep their header and fields, their members
+    // get edges of their own
+    let shells: Vec<(Vertex, String)> = funct_set
+        .namespace_items
+        .keys()
+        .map(|name| (namespace_key(name), funct_set.shell(name)))
+        .collect();
+    let items = shells.iter().map(|(vertex, code)| (vertex.clone(), code.as_str(), "this"));
+    let members = funct_set.object_members.iter().flat_map(|(object, members)| {
+        members.iter().map(move |(member, code)| (member_key(object, member), code.as_str(), "this"))
+    });
+    // `this` isn't a DataFrame inside other classes, their own methods are found by name
+    let class_members = funct_set.class_members.iter().flat_map(|(class, members)| {
+        members.iter().map(move |(member, code)| (member_key(class, member), code.as_str(), NAMESPACE))
+    });
+    let methods = funct_set
+        .dataframe_methods
+        .iter()
+        .map(|(name, code)| (method_key(name), code.as_str(), "this"));
+
+    for (vertex, code, initial) in items.chain(members).chain(class_members).chain(methods) {
+        //Parse the code for the method
+        let mut parser = backend.analyzer();
+        parser.set_callback_types(&callbacks);
+        parser.parse(code, initial);
+
+        let mut callees = call_targets(parser.as_ref());
+        // An item's own declaration name isn't a call to itself
+        callees.extend(
+            namespace_references(code, library)
+                .into_iter()
+                .chain(member_references(code, library))
+                .filter(|callee| *callee != vertex),
+        );
+        callees.sort();
+        callees.dedup();
+
+        adj_list.insert(vertex, callees);
+    }
+
+    adj_list
+}
+
+/// Returns the vertices called by the code an analyzer has parsed: `fr.x` accesses
+/// become namespace items, members used on a callback's `FrostRow` the interface itself,
+/// and every other call a DataFrame method.
+pub fn call_targets(analyzer: &dyn UsageAnalyzer) -> Vec<Vertex> {
+    let mut targets: Vec<Vertex> = analyzer
+        .get_namespace_calls()
+        .iter()
+        .map(|name| namespace_key(name))
+        .chain(analyzer.get_method_calls().iter().map(|name| method_key(name)))
+        .chain(analyzer.get_interface_calls().iter().map(|(interface, _)| namespace_key(interface)))
+        .collect();
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+/// Returns the namespace item vertices referenced by name in `code`, such as
+/// `fr::detectColumn` for a bare `detectColumn(...)` call.
+pub fn namespace_references(code: &str, funct_set: &FrostFunctionSet) -> Vec<Vertex> {
+    let mut references: Vec<Vertex> = bare_identifiers(code)
+        .into_iter()
+        .filter(|name| funct_set.namespace_items.contains_key(name))
+        .map(|name| namespace_key(&name))
+        .collect();
+    references.sort();
+    references
+}
+
+/// Returns the members referenced in `code`:
+/// - object literal members like `fr::predicates::is_blank` for
+///   `fr.predicates.is_blank(v)`. An object used as a whole, like in
+///   `Object.keys(fr.predicates)`, references every one of its members
+/// - static methods like `fr::DataFrame::from_rows` for `DataFrame.from_rows(rows)`
+/// - methods of classes other than DataFrame like `fr::Series::sum` for any `.sum`
+pub fn member_references(code: &str, funct_set: &FrostFunctionSet) -> Vec<Vertex> {
+    let tokens = code_tokens(code);
+    let mut references = Vec::new();
+    for (i, tok) in tokens.iter().enumerate() {
+        if tok.kind != TokenKind::Ident {
+            continue;
+        }
+        let is_member = i > 0 && (tokens[i - 1].is(".") || tokens[i - 1].is("?."));
+        if is_member {
+            references.extend(
+                funct_set
+                    .class_members
+                    .iter()
+                    .filter(|(_, members)| members.contains_key(tok.text))
+                    .map(|(class, _)| member_key(class, tok.text)),
+            );
+        }
+
+        let (members, is_class) = if tok.is(DATAFRAME) {
+            (&funct_set.dataframe_methods, true)
+        } else if let Some(members) = funct_set.class_members.get(tok.text) {
+            (members, true)
+        } else if let Some(members) = funct_set.object_members.get(tok.text) {
+            (members, false)
+        } else {
+            continue;
+        };
+        let key = |member: &str| if tok.is(DATAFRAME) { method_key(member) } else { member_key(tok.text, member) };
+
+        // An item's own declaration isn't a use of its members
+        if i > 0 && ["const", "let", "var", "class"].contains(&tokens[i - 1].text) {
+            continue;
+        }
+        // Only `predicates` itself or `fr.predicates`, not `other.predicates`
+        let is_qualified = i > 1 && tokens[i - 2].is(NAMESPACE) && !(i > 2 && tokens[i - 3].is("."));
+        if is_member && !is_qualified {
+            continue;
+        }
+
+        let next = tokens.get(i + 1);
+        let accessed = if next.is_some_and(|t| t.is(".") || t.is("?.")) {
+            tokens.get(i + 2).filter(|t| t.kind == TokenKind::Ident).map(|t| t.text)
+        } else if next.is_some_and(|t| t.is("[")) && tokens.get(i + 3).is_some_and(|t| t.is("]")) {
+            tokens.get(i + 2).filter(|t| t.kind == TokenKind::String).map(|t| &t.text[1..t.text.len() - 1])
+        } else {
+            None
+        };
+
+        match accessed {
+            Some(member) if members.contains_key(member) => references.push(key(member)),
+            // Built-in members like `hasOwnProperty` don't need any of the object's own,
+            // and a class used as a type or constructed doesn't need its static methods
+            Some(_) => {}
+            None if is_class => {}
+            None => references.extend(members.keys().map(|member| key(member))),
+        }
+    }
+    references.sort();
+    references.dedup();
+    references
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     // Minimal FrostFunctionSet mock (already defined elsewhere in your project)
     use crate::compile::source::{FrostFunctionSet};
+    use std::collections::HashMap;
     fn make_frost_set(methods: &[(&str, &str)]) -> FrostFunctionSet {
         let dataframe_methods = methods
             .iter()
@@ -108,8 +308,11 @@ mod tests {
             .collect();
         FrostFunctionSet {
             always_take: String::new(),
+            namespace_items: IndexMap::new(),
+            object_members: IndexMap::new(),
+            class_members: IndexMap::new(),
             dataframe_methods,
-            problematic_methods: HashMap::new()
+            spans: HashMap::new(),
         }
     }
 
@@ -117,13 +320,21 @@ mod tests {
         strings.iter().map(|s| s.to_string()).collect()
     }
 
+    fn m(name: &str) -> Vertex {
+        method_key(name)
+    }
+
+    fn methods(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| m(name)).collect()
+    }
+
     #[test]
     fn graph_single_function_no_calls() {
         let funct_set = make_frost_set(&[("filter", "")]);
         let graph = Graph::from_function_set(&funct_set);
 
         assert_eq!(graph.n, 1);
-        assert_eq!(graph.adj_list["filter"], Vec::<String>::new());
+        assert_eq!(graph.adj_list[&m("filter")], Vec::<String>::new());
     }
 
     #[test]
@@ -131,7 +342,7 @@ mod tests {
         let funct_set = make_frost_set(&[("print", "this.print();")]);
         let graph = Graph::from_function_set(&funct_set);
 
-        assert_eq!(graph.adj_list["print"], vec!["print"]);
+        assert_eq!(graph.adj_list[&m("print")], vec![m("print")]);
     }
 
     #[test]
@@ -142,8 +353,8 @@ mod tests {
         ]);
         let graph = Graph::from_function_set(&funct_set);
 
-        assert_eq!(graph.adj_list["head"], vec!["tail"]);
-        assert_eq!(graph.adj_list["tail"], Vec::<String>::new());
+        assert_eq!(graph.adj_list[&m("head")], vec![m("tail")]);
+        assert_eq!(graph.adj_list[&m("tail")], Vec::<String>::new());
     }
 
     #[test]
@@ -151,7 +362,7 @@ mod tests {
         let funct_set = make_frost_set(&[("apply", "this.map();")]);
         let graph = Graph::from_function_set(&funct_set);
 
-        assert!(graph.adj_list["apply"].is_empty()); // map isn't defined
+        assert!(graph.adj_list[&m("apply")].is_empty()); // map isn't defined
     }
 
     #[test]
@@ -162,8 +373,8 @@ mod tests {
         ]);
         let graph = Graph::from_function_set(&funct_set);
 
-        let result = graph.search(&vec!["to_csv".to_string()]);
-        let expected = set(&["to_csv", "to_array"]);
+        let result = graph.search(&[m("to_csv")]);
+        let expected = methods(&["to_csv", "to_array"]);
 
         assert_eq!(result, expected);
     }
@@ -178,8 +389,8 @@ mod tests {
         ]);
         let graph = Graph::from_function_set(&funct_set);
 
-        let result = graph.search(&vec!["print".into(), "filter".into()]);
-        let expected = set(&["print", "head", "tail", "filter"]);
+        let result = graph.search(&[m("print"), m("filter")]);
+        let expected = methods(&["print", "head", "tail", "filter"]);
 
         assert_eq!(result, expected);
     }
@@ -192,8 +403,8 @@ mod tests {
         ]);
         let graph = Graph::from_function_set(&funct_set);
 
-        let result = graph.search(&vec!["not_defined".into(), "drop".into()]);
-        let expected = set(&["drop", "copy"]);
+        let result = graph.search(&[m("not_defined"), m("drop")]);
+        let expected = methods(&["drop", "copy"]);
 
         assert_eq!(result, expected);
     }
@@ -208,9 +419,110 @@ mod tests {
         ]);
         let graph = Graph::from_function_set(&funct_set);
 
-        let result = graph.search(&vec!["a".into()]);
-        let expected = set(&["a", "b", "c", "d"]);
+        let result = graph.search(&[m("a")]);
+        let expected = methods(&["a", "b", "c", "d"]);
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn graph_links_methods_and_namespace_items() {
+        let mut funct_set = make_frost_set(&[
+            ("constructor", "constructor(data) { this.dtypes = detectColumn(data); }"),
+            ("describe", ""),
+        ]);
+        funct_set.namespace_items = IndexMap::from([
+            ("detectColumn".to_string(), "function detectColumn(col) { return parseValue(col); }".to_string()),
+            ("parseValue".to_string(), "function parseValue(v) { return v; }".to_string()),
+            ("summarize".to_string(), "function summarize(df: DataFrame) { return df.describe(); }".to_string()),
+            ("unused".to_string(), "const unused = 1;".to_string()),
+        ]);
+        let graph = Graph::from_function_set(&funct_set);
+
+        assert_eq!(graph.adj_list[&m("constructor")], vec!["fr::detectColumn"]);
+        assert_eq!(graph.adj_list["fr::summarize"], vec!["fr::DataFrame::describe"]);
+
+        let result = graph.search(&[m("constructor")]);
+        assert_eq!(result, set(&["fr::DataFrame::constructor", "fr::detectColumn", "fr::parseValue"]));
+    }
+
+    #[test]
+    fn graph_follows_dataframes_created_in_helpers() {
+        let mut funct_set = make_frost_set(&[("constructor", ""), ("rename", "")]);
+        funct_set.namespace_items = IndexMap::from([(
+            "from_rows".to_string(),
+            "function from_rows(rows) { let out = new DataFrame(rows); return out.rename({}); }".to_string(),
+        )]);
+        let graph = Graph::from_function_set(&funct_set);
+
+        assert_eq!(graph.adj_list["fr::from_rows"], vec![m("constructor"), m("rename")]);
+    }
+
+    #[test]
+    fn graph_splits_object_literals_into_members() {
+        let mut funct_set = make_frost_set(&[("constructor", "")]);
+        funct_set.namespace_items = IndexMap::from([
+            ("predicates".to_string(), "const predicates = { is_blank: (v) => v == \"\", equal: (t) => (v) => check(v, t) };".to_string()),
+            ("check".to_string(), "function check(v, t) { return v == t; }".to_string()),
+            ("not".to_string(), "const not = (p) => (v) => !p(v);".to_string()),
+        ]);
+        funct_set.object_members = IndexMap::from([(
+            "predicates".to_string(),
+            IndexMap::from([
+                ("is_blank".to_string(), "is_blank: (v) => v == \"\",".to_string()),
+                ("equal".to_string(), "equal: (t) => (v) => check(v, t)".to_string()),
+            ]),
+        )]);
+        funct_set.always_take = funct_set.namespace_items.values().cloned().collect::<Vec<_>>().join("\n");
+        let span = |vertex: &str, code: &str| {
+            let start = funct_set.always_take.find(code).unwrap();
+            (vertex.to_string(), start..start + code.len())
+        };
+        funct_set.spans = HashMap::from([
+            span("fr::predicates", &funct_set.namespace_items["predicates"]),
+            span("fr::predicates::is_blank", "is_blank: (v) => v == \"\","),
+            span("fr::predicates::equal", "equal: (t) => (v) => check(v, t)"),
+        ]);
+        let graph = Graph::from_function_set(&funct_set);
+
+        assert!(graph.adj_list["fr::predicates"].is_empty());
+        assert_eq!(graph.adj_list["fr::predicates::equal"], vec!["fr::check", "fr::predicates"]);
+
+        let roots = member_references("df.filter(\"x\", fr.not(fr.predicates.equal(1)))", &funct_set);
+        assert_eq!(roots, vec!["fr::predicates::equal"]);
+        assert_eq!(
+            graph.search(&roots),
+            set(&["fr::predicates::equal", "fr::predicates", "fr::check"])
+        );
+
+        let whole = member_references("Object.keys(fr.predicates); other.predicates.nope", &funct_set);
+        assert_eq!(whole, vec!["fr::predicates::equal", "fr::predicates::is_blank"]);
+    }
+
+    #[test]
+    fn graph_gates_class_members_on_their_class() {
+        let mut funct_set = make_frost_set(&[("constructor", ""), ("total", "total() { return this.values.sum(); }")]);
+        funct_set.namespace_items = IndexMap::from([
+            ("Series".to_string(), "class Series { values: Row[]; sum() { return 0; } }".to_string()),
+            ("Row".to_string(), "type Row = { [key: string]: number };".to_string()),
+            ("DataFrame".to_string(), "class DataFrame { }".to_string()),
+        ]);
+        funct_set.class_members = IndexMap::from([(
+            "Series".to_string(),
+            IndexMap::from([("sum".to_string(), "sum() { return 0; }".to_string())]),
+        )]);
+        let graph = Graph::from_function_set(&funct_set);
+
+        assert_eq!(graph.adj_list["fr::Series"], vec!["fr::Row"]);
+        assert_eq!(graph.adj_list[&m("total")], vec!["fr::Series::sum", "fr::DataFrame"]);
+        assert_eq!(graph.gates["fr::Series::sum"], "fr::Series");
+
+        // `.sum` alone doesn't keep Series
+        let result = graph.search(&[m("total")]);
+        assert_eq!(result, set(&["fr::DataFrame::total", "fr::DataFrame", "fr::DataFrame::constructor"]));
+
+        // Once Series is used, the parked member follows, in either order
+        let result = graph.search(&[m("total"), "fr::Series".to_string()]);
+        assert!(result.contains("fr::Series::sum") && result.contains("fr::Row"));
+    }
 }
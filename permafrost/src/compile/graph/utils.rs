@@ -1,12 +1,27 @@
 //Utility Type Aliases for graph
-use std::collections::HashMap;
+use indexmap::IndexMap;
 
 pub type Vertex = String;
 pub type EdgeList = Vec<Vertex>;
-pub type AdjList = HashMap<String,EdgeList>;
+pub type AdjList = IndexMap<String,EdgeList>;
 
 
 /// Read an input string s and strip the generic typic
 pub fn strip_generics(s: &str) -> String {
     s.split('<').next().unwrap_or(s).to_string()
 }
+
+/// The vertex for a top-level `fr` namespace item, e.g. `fr::read_csv`.
+pub fn namespace_key(name: &str) -> Vertex {
+    format!("fr::{}", name)
+}
+
+/// The vertex for a member of an object literal namespace item, e.g. `fr::predicates::is_blank`.
+pub fn member_key(object: &str, member: &str) -> Vertex {
+    format!("fr::{}::{}", object, member)
+}
+
+/// The vertex for a DataFrame method, e.g. `fr::DataFrame::merge` for `merge` or `merge<T>`.
+pub fn method_key(name: &str) -> Vertex {
+    format!("fr::DataFrame::{}", strip_generics(name))
+}
@@ -0,0 +1,128 @@
+//! # header
+//!
+//! The comment permafrost writes on the first line of every compiled script, like
+//! `// permafrost: frosts=9f3c2a1b7e4d5c60 kept=3:33abeffbeb5ebf8e`.
+//!
+//! It records which frosts namespace the script was compiled from and how many symbols
+//! were kept with a fingerprint of them, so compiled scripts can be recognised when
+//! they're compiled again. The kept symbols themselves are listed by `explain`, which
+//! keeps the header short enough not to outweigh what shaking saves.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Starts the header line.
+pub const MARKER: &str = "// permafrost:";
+
+/// The parsed header of a compiled script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// Fingerprint of the full `fr` namespace the script was first compiled from
+    pub fingerprint: String,
+    /// Number of kept symbols
+    pub kept: usize,
+    /// Fingerprint of the kept symbols, which changes whenever the set does
+    pub kept_fingerprint: String,
+}
+
+impl Header {
+    /// Builds the header of a script compiled from the namespace with `fingerprint`.
+    pub fn new(fingerprint: String, kept: &BTreeSet<String>) -> Self {
+        let symbols: Vec<&str> = kept.iter().map(String::as_str).collect();
+        Self {
+            fingerprint,
+            kept: kept.len(),
+            kept_fingerprint: self::fingerprint(&symbols.join("\n")),
+        }
+    }
+
+    /// Parses the header from the first line of `line`, if it is one.
+    pub fn parse(line: &str) -> Option<Self> {
+        let rest = line.lines().next()?.strip_prefix(MARKER)?;
+        let mut fingerprint = None;
+        let mut kept = (0, String::new());
+        for field in rest.split_whitespace() {
+            match field.split_once('=') {
+                Some(("frosts", value)) => fingerprint = Some(value.to_string()),
+                Some(("kept", value)) => {
+                    if let Some((count, hash)) = value.split_once(':') {
+                        kept = (count.parse().unwrap_or_default(), hash.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(Self { fingerprint: fingerprint?, kept: kept.0, kept_fingerprint: kept.1 })
+    }
+
+    /// Splits a script into its header and the code after it. Scripts without a header
+    /// are returned whole.
+    pub fn strip(input: &str) -> (Option<Self>, &str) {
+        match Self::parse(input) {
+            Some(header) => {
+                let rest = input.find('\n').map_or("", |newline| &input[newline + 1..]);
+                (Some(header), rest)
+            }
+            None => (None, input),
+        }
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} frosts={} kept={}:{}", MARKER, self.fingerprint, self.kept, self.kept_fingerprint)
+    }
+}
+
+/// Fingerprints a `fr` namespace with 64-bit FNV-1a, so the same frosts source gives the
+/// same fingerprint on every platform and toolchain. Line endings and trailing
+/// whitespace don't count.
+pub fn fingerprint(fr: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for line in fr.lines() {
+        for byte in line.trim_end().bytes().chain(std::iter::once(b'\n')) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_round_trip() {
+        let kept = BTreeSet::from(["fr::DataFrame".to_string(), "fr::DataFrame::head".to_string()]);
+        let header = Header::new("00ff00ff00ff00ff".to_string(), &kept);
+        let line = header.to_string();
+
+        assert_eq!(
+            line,
+            format!("// permafrost: frosts=00ff00ff00ff00ff kept=2:{}", fingerprint("fr::DataFrame\nfr::DataFrame::head"))
+        );
+        assert_eq!(Header::parse(&line), Some(header));
+        assert_ne!(Header::new("00ff00ff00ff00ff".to_string(), &BTreeSet::new()).kept_fingerprint, fingerprint("fr::DataFrame"));
+    }
+
+    #[test]
+    fn strip_only_removes_a_leading_header() {
+        let (header, rest) = Header::strip("// permafrost: frosts=abc kept=0:def\nnamespace fr {}\n");
+        assert_eq!(header.map(|h| h.fingerprint), Some("abc".to_string()));
+        assert_eq!(rest, "namespace fr {}\n");
+
+        let script = "namespace fr {}\n// permafrost: frosts=abc kept=0:def\n";
+        assert_eq!(Header::strip(script), (None, script));
+        assert_eq!(Header::parse("// permafrost: kept=1:def"), None);
+    }
+
+    #[test]
+    fn fingerprints_ignore_line_endings() {
+        let fr = "namespace fr {\n    export let x = 1;\n}";
+
+        assert_eq!(fingerprint(fr), fingerprint(&fr.replace('\n', "\r\n")));
+        assert_ne!(fingerprint(fr), fingerprint(&fr.replace('1', "2")));
+        assert_eq!(fingerprint(fr).len(), 16);
+    }
+}
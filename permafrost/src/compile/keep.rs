@@ -0,0 +1,173 @@
+//! # keep
+//!
+//! Symbols to keep or drop regardless of what usage analysis finds, for code it can't
+//! see through, like `df[methodName]()` or methods passed around as values.
+//!
+//! Lists come from a `permafrost.toml` next to the script:
+//!
+//! ```toml
+//! keep = ["melt", "fr.read_csv"]
+//! drop = ["fr::DataFrame::to_worksheet"]
+//! ```
+//!
+//! or from directives in the script itself, like `// permafrost-keep: melt, pivot` and
+//! `// permafrost-drop: to_worksheet`.
+
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use super::error::CompileError;
+use super::explain::short_name;
+use super::graph::utils::Vertex;
+use super::lexer::{tokenize, TokenKind};
+
+/// The config file read from the directory of the script being compiled.
+pub const CONFIG_FILE: &str = "permafrost.toml";
+
+/// Starts a comment listing symbols to keep.
+pub const KEEP_DIRECTIVE: &str = "permafrost-keep:";
+
+/// Starts a comment listing symbols to drop.
+pub const DROP_DIRECTIVE: &str = "permafrost-drop:";
+
+/// Returns true if a `// ...` comment is a keep or drop directive. Directives survive
+/// comment stripping, so compiled scripts still carry them when compiled again.
+pub fn is_directive(comment: &str) -> bool {
+    let comment = comment.trim_start_matches('/').trim_start();
+    comment.starts_with(KEEP_DIRECTIVE) || comment.starts_with(DROP_DIRECTIVE)
+}
+
+/// Symbols to keep or drop, written as in code like `melt` or `fr.read_csv`, or as
+/// full paths like `fr::DataFrame::melt`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeepList {
+    /// Kept along with everything they use, as if the script called them
+    pub keep: Vec<String>,
+    /// Removed from the output even when the script appears to use them
+    pub drop: Vec<String>,
+}
+
+impl KeepList {
+    /// Parses a `permafrost.toml` config.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CompileError::InvalidConfig` if the file isn't valid TOML or has keys
+    /// other than `keep` and `drop`.
+    pub fn from_toml(text: &str, file: &Path) -> Result<Self, CompileError> {
+        toml::from_str(text).map_err(|e| CompileError::InvalidConfig {
+            file: file.to_path_buf(),
+            message: e.message().to_string(),
+        })
+    }
+
+    /// Reads and parses the `permafrost.toml` config at `path`.
+    pub fn read(path: &Path) -> Result<Self, CompileError> {
+        let text = fs::read_to_string(path).map_err(|source| CompileError::Io {
+            file: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_toml(&text, path)
+    }
+
+    /// Reads the `permafrost.toml` next to `script`, or returns an empty list if there
+    /// isn't one.
+    pub fn for_script(script: &Path) -> Result<Self, CompileError> {
+        let path = script.with_file_name(CONFIG_FILE);
+        if path.is_file() {
+            Self::read(&path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Collects the `// permafrost-keep:` and `// permafrost-drop:` directives in a
+    /// script. Names are separated by commas or whitespace.
+    pub fn from_directives(code: &str) -> Self {
+        let mut list = Self::default();
+        for token in tokenize(code).into_iter().filter(|t| t.kind == TokenKind::LineComment) {
+            let comment = token.text.trim_start_matches('/').trim();
+            let (names, target) = if let Some(names) = comment.strip_prefix(KEEP_DIRECTIVE) {
+                (names, &mut list.keep)
+            } else if let Some(names) = comment.strip_prefix(DROP_DIRECTIVE) {
+                (names, &mut list.drop)
+            } else {
+                continue;
+            };
+            target.extend(
+                names
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|name| !name.is_empty())
+                    .map(String::from),
+            );
+        }
+        list
+    }
+
+    /// Adds the names of `other` after these ones.
+    pub fn extend(&mut self, other: KeepList) {
+        self.keep.extend(other.keep);
+        self.drop.extend(other.drop);
+    }
+}
+
+/// Finds the symbol a name refers to among `defined`. Names can be written as in code,
+/// like `merge` or `fr.read_csv`, with or without their `fr.`, or as a full `fr::` path.
+/// A bare name that is both a DataFrame method and a namespace item, like `count`,
+/// means the method.
+pub fn resolve<'a>(name: &str, defined: impl IntoIterator<Item = &'a Vertex> + Clone) -> Option<Vertex> {
+    let exact = defined.clone().into_iter().find(|vertex| *vertex == name || short_name(vertex) == name);
+    exact
+        .or_else(|| {
+            defined
+                .into_iter()
+                .find(|vertex| short_name(vertex).strip_prefix("fr.") == Some(name))
+        })
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directives_list_names_to_keep_and_drop() {
+        let code = r#"
+            // permafrost-keep: melt, pivot
+            //permafrost-keep: fr.read_csv
+            let method = "melt"; // permafrost-drop: to_worksheet describe
+            let text = "// permafrost-keep: not_a_directive";
+            /* permafrost-keep: block_comments_are_ignored */
+        "#;
+        let list = KeepList::from_directives(code);
+
+        assert_eq!(list.keep, ["melt", "pivot", "fr.read_csv"]);
+        assert_eq!(list.drop, ["to_worksheet", "describe"]);
+    }
+
+    #[test]
+    fn config_files_list_names_to_keep_and_drop() {
+        let list = KeepList::from_toml("keep = [\"melt\"]\n", Path::new("permafrost.toml")).unwrap();
+        assert_eq!(list, KeepList { keep: vec!["melt".to_string()], drop: Vec::new() });
+
+        let err = KeepList::from_toml("keeps = [\"melt\"]\n", Path::new("permafrost.toml")).unwrap_err();
+        assert!(err.to_string().starts_with("permafrost.toml: invalid config: unknown field `keeps`"));
+    }
+
+    #[test]
+    fn names_resolve_to_symbols() {
+        let defined: Vec<Vertex> = ["fr::DataFrame::count", "fr::count", "fr::read_csv", "fr::predicates::equal"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let resolve = |name| resolve(name, &defined);
+
+        assert_eq!(resolve("count").as_deref(), Some("fr::DataFrame::count"));
+        assert_eq!(resolve("fr.count").as_deref(), Some("fr::count"));
+        assert_eq!(resolve("read_csv").as_deref(), Some("fr::read_csv"));
+        assert_eq!(resolve("fr::predicates::equal").as_deref(), Some("fr::predicates::equal"));
+        assert_eq!(resolve("predicates.equal").as_deref(), Some("fr::predicates::equal"));
+        assert_eq!(resolve("melt"), None);
+    }
+}
@@ -0,0 +1,556 @@
+//! # compile::lexer
+//!
+//! A small TypeScript tokenizer shared by every compilation stage.
+//!
+//! Source splitting, method extraction, comment stripping and call detection all work
+//! on the same token stream, so they agree on what is code and what sits inside a
+//! string, template literal, regex or comment.
+
+/// The category of a lexed token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Identifiers and keywords
+    Ident,
+    /// Numeric literals, including `100_000` and `0x1F`
+    Number,
+    /// `'...'` and `"..."` string literals
+    String,
+    /// A literal chunk of a template string. A template with substitutions is split
+    /// into chunks (`` `a ${ ``, `} b ${ ``, `` } c` ``) with the substituted
+    /// expressions lexed as ordinary tokens in between.
+    Template,
+    /// Regular expression literals such as `/^-?\d+$/g`
+    Regex,
+    /// Operators and punctuation
+    Punct,
+    /// `// ...` comments
+    LineComment,
+    /// `/* ... */` comments
+    BlockComment,
+    /// Runs of spaces, tabs and newlines
+    Whitespace,
+}
+
+/// A single token with its position in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    /// The exact source text of the token
+    pub text: &'a str,
+    /// Byte offset of the token in the source
+    pub start: usize,
+    /// 1-based line the token starts on
+    pub line: usize,
+    /// True if a line break separates this token from the previous code token
+    pub newline_before: bool,
+}
+
+impl Token<'_> {
+    /// Byte offset just past the end of the token.
+    pub fn end(&self) -> usize {
+        self.start + self.text.len()
+    }
+
+    /// Returns true if this is a punctuator or identifier with exactly this text.
+    pub fn is(&self, text: &str) -> bool {
+        matches!(self.kind, TokenKind::Punct | TokenKind::Ident) && self.text == text
+    }
+
+    /// Returns true for whitespace and comments.
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self.kind,
+            TokenKind::Whitespace | TokenKind::LineComment | TokenKind::BlockComment
+        )
+    }
+}
+
+/// Multi-character punctuators, longest first. `>>` is deliberately absent so the
+/// closing brackets of nested generics (`Map<string, Set<number>>`) stay separate.
+const PUNCTUATORS: [&str; 28] = [
+    "...", "===", "!==", "**=", "<<=", "&&=", "||=", "??=", "=>", "==", "!=", "<=", ">=", "&&",
+    "||", "??", "?.", "++", "--", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "**",
+];
+
+/// Keywords after which a `/` starts a regex literal rather than a division.
+const REGEX_PREFIX_KEYWORDS: [&str; 14] = [
+    "return", "typeof", "instanceof", "in", "of", "new", "delete", "void", "throw", "case", "do",
+    "else", "yield", "await",
+];
+
+/// Splits `src` into tokens, including whitespace and comments.
+///
+/// Concatenating the text of every token reproduces `src` exactly.
+pub fn tokenize(src: &str) -> Vec<Token<'_>> {
+    Lexer::new(src).run()
+}
+
+/// Splits `src` into code tokens only, dropping whitespace and comments.
+/// Line breaks are recorded on each token through `newline_before`.
+pub fn code_tokens(src: &str) -> Vec<Token<'_>> {
+    tokenize(src).into_iter().filter(|t| !t.is_trivia()).collect()
+}
+
+/// Given the index of an opening `(`, `[` or `{`, returns the index of its matching closer.
+pub fn matching_close(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, tok) in tokens.iter().enumerate().skip(open) {
+        if tok.kind != TokenKind::Punct {
+            continue;
+        }
+        match tok.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the exclusive end index of the statement (or class member) starting at `start`.
+///
+/// A statement ends at a `;` outside any brackets (which is included), just before a
+/// bracket that closes an enclosing scope, or at a line break where automatic semicolon
+/// insertion would apply, meaning neither side of the break continues the expression.
+pub fn statement_end(tokens: &[Token], start: usize) -> usize {
+    let mut depth = 0usize;
+    let mut i = start;
+
+    while i < tokens.len() {
+        let tok = &tokens[i];
+
+        if depth == 0 && i > start && tok.newline_before && !continues(&tokens[i - 1], tok) {
+            return i;
+        }
+
+        if tok.kind == TokenKind::Punct {
+            match tok.text {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => {
+                    if depth == 0 {
+                        return i;
+                    }
+                    depth -= 1;
+                }
+                ";" if depth == 0 => return i + 1,
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    tokens.len()
+}
+
+/// Splits `tokens[lo..hi]` into consecutive statements, skipping empty `;` statements.
+/// Each statement is returned as a `(start, end)` index pair.
+pub fn split_statements(tokens: &[Token], lo: usize, hi: usize) -> Vec<(usize, usize)> {
+    let mut statements = Vec::new();
+    let mut i = lo;
+    while i < hi {
+        if tokens[i].is(";") {
+            i += 1;
+            continue;
+        }
+        let end = statement_end(&tokens[..hi], i).max(i + 1);
+        statements.push((i, end));
+        i = end;
+    }
+    statements
+}
+
+/// Splits a comma-separated list in `tokens[lo..hi]`, like call arguments or parameters,
+/// at its top-level commas. Each item is returned as a `(start, end)` index pair.
+///
+/// `<` and `>` only nest when `generics` is set, since outside of types they're comparisons.
+pub fn split_list(tokens: &[Token], lo: usize, hi: usize, generics: bool) -> Vec<(usize, usize)> {
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut start = lo;
+    for (i, tok) in tokens.iter().enumerate().take(hi).skip(lo) {
+        if tok.kind != TokenKind::Punct {
+            continue;
+        }
+        match tok.text {
+            "(" | "[" | "{" => depth += 1,
+            "<" if generics => depth += 1,
+            ")" | "]" | "}" => depth = depth.saturating_sub(1),
+            ">" if generics => depth = depth.saturating_sub(1),
+            "," if depth == 0 => {
+                items.push((start, i));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < hi {
+        items.push((start, hi));
+    }
+    items
+}
+
+/// Returns true if the code on either side of a line break belongs to the same statement.
+fn continues(prev: &Token, next: &Token) -> bool {
+    const TRAILING: [&str; 50] = [
+        ".", "?.", ",", "=", ":", "=>", "|", "&", "+", "-", "*", "/", "%", "&&", "||", "??", "?",
+        "<", "(", "[", "{", "+=", "-=", "*=", "/=", "==", "===", "!=", "!==", "<=", ">=", "!",
+        "...", "extends", "implements", "new", "typeof", "in", "of", "instanceof", "as", "export",
+        "const", "let", "var", "function", "class", "async", "await", "keyof",
+    ];
+    const LEADING: [&str; 27] = [
+        ".", "?.", ",", "=", ":", "=>", "|", "&", "&&", "||", "??", "?", "{", "+", "*", "/", "%",
+        "==", "===", "!=", "!==", ">", ">=", "<=", "as", "extends", "implements",
+    ];
+
+    let prev_continues = match prev.kind {
+        TokenKind::Punct | TokenKind::Ident => TRAILING.contains(&prev.text),
+        TokenKind::Template => prev.text.ends_with("${"),
+        _ => false,
+    };
+    let next_continues = match next.kind {
+        TokenKind::Punct | TokenKind::Ident => LEADING.contains(&next.text),
+        _ => false,
+    };
+    prev_continues || next_continues
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    line: usize,
+    tokens: Vec<Token<'a>>,
+    /// Open braces; `true` marks a template `${` substitution
+    braces: Vec<bool>,
+    newline_pending: bool,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(src: &'a str) -> Self {
+        Lexer {
+            src,
+            bytes: src.as_bytes(),
+            pos: 0,
+            line: 1,
+            tokens: Vec::new(),
+            braces: Vec::new(),
+            newline_pending: false,
+        }
+    }
+
+    fn run(mut self) -> Vec<Token<'a>> {
+        while self.pos < self.bytes.len() {
+            let start = self.pos;
+            let b = self.bytes[start];
+            let next = self.bytes.get(start + 1).copied();
+
+            let kind = match b {
+                b' ' | b'\t' | b'\r' | b'\n' | 0x0B | 0x0C => {
+                    self.eat_while(|c| c.is_ascii_whitespace() || c == 0x0B);
+                    TokenKind::Whitespace
+                }
+                b'/' if next == Some(b'/') => {
+                    self.eat_while(|c| c != b'\n');
+                    TokenKind::LineComment
+                }
+                b'/' if next == Some(b'*') => {
+                    self.pos = match self.src[start + 2..].find("*/") {
+                        Some(offset) => start + 2 + offset + 2,
+                        None => self.bytes.len(),
+                    };
+                    TokenKind::BlockComment
+                }
+                b'/' if self.regex_allowed() && self.eat_regex() => TokenKind::Regex,
+                b'"' | b'\'' => {
+                    self.eat_string(b);
+                    TokenKind::String
+                }
+                b'`' => {
+                    self.pos += 1;
+                    self.eat_template_chunk();
+                    TokenKind::Template
+                }
+                b'}' if self.braces.last() == Some(&true) => {
+                    self.braces.pop();
+                    self.pos += 1;
+                    self.eat_template_chunk();
+                    TokenKind::Template
+                }
+                b'0'..=b'9' => {
+                    self.eat_number();
+                    TokenKind::Number
+                }
+                b'.' if next.is_some_and(|c| c.is_ascii_digit()) => {
+                    self.eat_number();
+                    TokenKind::Number
+                }
+                b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'$' => {
+                    self.eat_ident();
+                    TokenKind::Ident
+                }
+                0x80.. => {
+                    let c = self.src[start..].chars().next().unwrap_or(' ');
+                    if c.is_alphabetic() {
+                        self.eat_ident();
+                        TokenKind::Ident
+                    } else {
+                        self.pos += c.len_utf8();
+                        TokenKind::Punct
+                    }
+                }
+                _ => {
+                    self.eat_punct();
+                    TokenKind::Punct
+                }
+            };
+
+            self.push(kind, start);
+        }
+        self.tokens
+    }
+
+    fn push(&mut self, kind: TokenKind, start: usize) {
+        let text = &self.src[start..self.pos];
+        let is_trivia = matches!(
+            kind,
+            TokenKind::Whitespace | TokenKind::LineComment | TokenKind::BlockComment
+        );
+
+        let newline_before = !is_trivia && self.newline_pending;
+        if is_trivia && text.contains('\n') {
+            self.newline_pending = true;
+        } else if !is_trivia {
+            self.newline_pending = false;
+        }
+
+        self.tokens.push(Token {
+            kind,
+            text,
+            start,
+            line: self.line,
+            newline_before,
+        });
+        self.line += text.matches('\n').count();
+    }
+
+    fn eat_while(&mut self, pred: impl Fn(u8) -> bool) {
+        while self.pos < self.bytes.len() && pred(self.bytes[self.pos]) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat_ident(&mut self) {
+        while let Some(c) = self.src[self.pos..].chars().next() {
+            if c.is_alphanumeric() || c == '_' || c == '$' {
+                self.pos += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn eat_number(&mut self) {
+        let start = self.pos;
+        while self.pos < self.bytes.len() {
+            let c = self.bytes[self.pos];
+            let exponent_sign = (c == b'+' || c == b'-')
+                && matches!(self.bytes[self.pos - 1], b'e' | b'E')
+                && !self.src[start..].starts_with("0x");
+            if c.is_ascii_alphanumeric() || c == b'_' || c == b'.' || exponent_sign {
+                // Stop before `..` or a member access like `1.5.toFixed`
+                if c == b'.' && self.src[self.pos + 1..].starts_with(|n: char| !n.is_ascii_digit()) {
+                    break;
+                }
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn eat_string(&mut self, quote: u8) {
+        self.pos += 1;
+        while self.pos < self.bytes.len() {
+            match self.bytes[self.pos] {
+                b'\\' => self.pos += 2,
+                b'\n' => return,
+                c if c == quote => {
+                    self.pos += 1;
+                    return;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        self.pos = self.pos.min(self.bytes.len());
+    }
+
+    /// Consumes template text up to and including the closing backtick or a `${`.
+    fn eat_template_chunk(&mut self) {
+        while self.pos < self.bytes.len() {
+            match self.bytes[self.pos] {
+                b'\\' => self.pos += 2,
+                b'`' => {
+                    self.pos += 1;
+                    return;
+                }
+                b'$' if self.bytes.get(self.pos + 1) == Some(&b'{') => {
+                    self.pos += 2;
+                    self.braces.push(true);
+                    return;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        self.pos = self.pos.min(self.bytes.len());
+    }
+
+    /// Tries to consume a regex literal. Returns false (consuming nothing) if the
+    /// literal is unterminated on its line, in which case `/` is treated as division.
+    fn eat_regex(&mut self) -> bool {
+        let mut i = self.pos + 1;
+        let mut in_class = false;
+        while i < self.bytes.len() {
+            match self.bytes[i] {
+                b'\\' => i += 1,
+                b'\n' => return false,
+                b'[' => in_class = true,
+                b']' => in_class = false,
+                b'/' if !in_class => {
+                    self.pos = i + 1;
+                    self.eat_while(|c| c.is_ascii_alphabetic());
+                    return true;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        false
+    }
+
+    fn eat_punct(&mut self) {
+        let rest = &self.src[self.pos..];
+        // `a?.5:1` is a ternary, not optional chaining
+        let optional_chain_on_number =
+            rest.starts_with("?.") && rest[2..].starts_with(|c: char| c.is_ascii_digit());
+
+        if let Some(p) = PUNCTUATORS
+            .iter()
+            .find(|p| rest.starts_with(**p) && !(optional_chain_on_number && **p == "?."))
+        {
+            self.pos += p.len();
+            return;
+        }
+
+        match self.bytes[self.pos] {
+            b'{' => self.braces.push(false),
+            b'}' => {
+                self.braces.pop();
+            }
+            _ => {}
+        }
+        self.pos += 1;
+    }
+
+    /// Decides whether a `/` at the current position starts a regex, based on the
+    /// previous code token.
+    fn regex_allowed(&self) -> bool {
+        let Some(prev) = self.tokens.iter().rev().find(|t| !t.is_trivia()) else {
+            return true;
+        };
+        match prev.kind {
+            TokenKind::Punct => !matches!(prev.text, ")" | "]" | "}"),
+            TokenKind::Ident => REGEX_PREFIX_KEYWORDS.contains(&prev.text),
+            TokenKind::Template => prev.text.ends_with("${"),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds_and_text(src: &str) -> Vec<(TokenKind, &str)> {
+        code_tokens(src).into_iter().map(|t| (t.kind, t.text)).collect()
+    }
+
+    #[test]
+    fn tokens_reproduce_source() {
+        let src = "let a = `x ${b + `y${c}`} z`; // done\n/* block */ const r = /[/]+/g;";
+        let joined: String = tokenize(src).iter().map(|t| t.text).collect();
+        assert_eq!(joined, src);
+    }
+
+    #[test]
+    fn braces_inside_strings_are_not_punctuation() {
+        let toks = kinds_and_text(r#"let s = "{" + '}' + `${x}`;"#);
+        let braces = toks.iter().filter(|(k, t)| *k == TokenKind::Punct && (*t == "{" || *t == "}")).count();
+        assert_eq!(braces, 0);
+    }
+
+    #[test]
+    fn template_substitutions_are_lexed_as_code() {
+        let toks = kinds_and_text("`rows: ${df.shape()}`");
+        assert_eq!(toks[0], (TokenKind::Template, "`rows: ${"));
+        assert_eq!(toks[1], (TokenKind::Ident, "df"));
+        assert_eq!(toks[2], (TokenKind::Punct, "."));
+        assert_eq!(toks[3], (TokenKind::Ident, "shape"));
+        assert_eq!(toks.last().unwrap(), &(TokenKind::Template, "}`"));
+    }
+
+    #[test]
+    fn distinguishes_regex_from_division() {
+        let toks = kinds_and_text("if (/^-?\\d+$/.test(s)) { x = sum(n) / n.length; }");
+        assert_eq!(toks[2], (TokenKind::Regex, "/^-?\\d+$/"));
+        assert!(toks.contains(&(TokenKind::Punct, "/")));
+    }
+
+    #[test]
+    fn lexes_numbers_with_separators() {
+        let toks = kinds_and_text("v > 100_000 && x * 0.3");
+        assert!(toks.contains(&(TokenKind::Number, "100_000")));
+        assert!(toks.contains(&(TokenKind::Number, "0.3")));
+    }
+
+    #[test]
+    fn records_lines_and_newlines() {
+        let toks = code_tokens("a\n  // note\n  b c");
+        assert_eq!(toks[1].line, 3);
+        assert!(toks[1].newline_before);
+        assert!(!toks[2].newline_before);
+    }
+
+    #[test]
+    fn nested_generics_close_separately() {
+        let toks = kinds_and_text("Map<string, Set<number>>");
+        assert_eq!(toks[toks.len() - 2], (TokenKind::Punct, ">"));
+        assert_eq!(toks[toks.len() - 1], (TokenKind::Punct, ">"));
+    }
+
+    #[test]
+    fn splits_statements_with_and_without_semicolons() {
+        let src = "columns: string[]\nvalues: Row[];\nfoo(a: string,\n    b: number): DataFrame\n{\n    return this;\n}\nbar() {}";
+        let toks = code_tokens(src);
+        let statements = split_statements(&toks, 0, toks.len());
+        let first_words: Vec<&str> = statements.iter().map(|(s, _)| toks[*s].text).collect();
+        assert_eq!(first_words, ["columns", "values", "foo", "bar"]);
+    }
+
+    #[test]
+    fn matching_close_skips_nested_brackets() {
+        let toks = code_tokens("f(a(b), [c], { d })");
+        assert_eq!(matching_close(&toks, 1), Some(toks.len() - 1));
+    }
+
+    #[test]
+    fn splits_lists_at_top_level_commas() {
+        let toks = code_tokens("a < b, f(c, d), m: Map<string, number>");
+        let firsts = |items: Vec<(usize, usize)>| items.iter().map(|(s, _)| toks[*s].text).collect::<Vec<_>>();
+        assert_eq!(firsts(split_list(&toks, 0, toks.len(), false)), ["a", "f", "m", "number"]);
+        assert_eq!(firsts(split_list(&toks, 4, toks.len(), true)), ["f", "m"]);
+    }
+}
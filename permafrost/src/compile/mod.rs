@@ -16,38 +16,776 @@ pub mod utils;
 
 pub mod code_parser;
 
-/// Main compilation function
-use source::FrostSource;
-use code_parser::FunctionParser;
+/// Defines `CompileError`, returned by every stage of compilation.
+pub mod error;
 
-pub fn compile_from_string(input: &str) -> Result<String, String> {
-    let mut source = FrostSource::from_body(input);
+/// Tokenizes TypeScript source for every other stage of compilation.
+pub mod lexer;
+
+/// Explains why each kept symbol was reached.
+pub mod explain;
+
+/// Checks that compiled output defines everything it uses.
+pub mod verify;
+
+/// Reads and writes the header comment that marks compiled scripts.
+pub mod header;
 
-    // Preprocess source and remove comments
-    source.preprocess(true);
+/// Reads the symbols to keep or drop from `permafrost.toml` and script directives.
+pub mod keep;
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use indexmap::IndexMap;
+use explain::{Explanation, Origin};
+use graph::utils::{method_key, namespace_key};
+use source::{FrostFunctionSet, FrostSource};
+use code_parser::ParserBackend;
+use error::CompileError;
+use header::Header;
+use keep::KeepList;
+
+/// Settings that control how a script is compiled.
+///
+/// Marked `#[non_exhaustive]` so new settings can be added without breaking callers;
+/// construct it with `CompileOptions::default()` and override individual fields.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct CompileOptions {
+    /// Leave the user's code outside the `fr` namespace byte-for-byte intact,
+    /// including its comments and blank lines
+    pub preserve_main: bool,
+    /// Keep `/*! ... */` license-style comments inside the `fr` namespace
+    pub keep_license_comments: bool,
+    /// The analyzer used to find which methods the code calls
+    pub parser: ParserBackend,
+    /// Re-parse the compiled script and fail if it uses anything that was removed.
+    /// Uses of dropped symbols are warnings instead
+    pub verify: bool,
+    /// Symbols to keep or drop whatever usage analysis finds, usually read from
+    /// `permafrost.toml`. Directives in the script are added to these
+    pub keep_list: KeepList,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            preserve_main: false,
+            keep_license_comments: false,
+            parser: ParserBackend::default(),
+            verify: true,
+            keep_list: KeepList::default(),
+        }
+    }
+}
+
+/// The result of a successful compilation.
+#[derive(Debug, Clone)]
+pub struct CompileOutput {
+    /// The compiled script: a `Header` comment, the reduced `fr` namespace, then the
+    /// user's code
+    pub code: String,
+    /// Fingerprint of the full `fr` namespace. Compiling a compiled script keeps the
+    /// fingerprint from its header
+    pub fingerprint: String,
+    /// Every `fr` symbol kept in the output, e.g. `fr::read_csv` or `fr::DataFrame::head`
+    pub required: BTreeSet<String>,
+    /// Every `fr` symbol that was removed
+    pub dropped: BTreeSet<String>,
+    /// Why each symbol in `required` was kept, like
+    /// `main:line 12 df.describe() -> describe -> fr.getNumericColumns`
+    pub explanations: BTreeMap<String, Explanation>,
+    /// Size of the script before and after compilation
+    pub stats: SizeStats,
+    /// The call graph that was searched, without the dropped symbols
+    pub graph: graph::Graph,
+    /// Problems that didn't stop compilation, like unknown names in the keep list
+    pub warnings: Vec<String>,
+}
+
+/// Byte counts of a script before and after compilation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeStats {
+    pub input_bytes: usize,
+    pub output_bytes: usize,
+}
+
+impl SizeStats {
+    /// Number of bytes removed by compilation.
+    pub fn saved_bytes(&self) -> usize {
+        self.input_bytes.saturating_sub(self.output_bytes)
+    }
+
+    /// Fraction of the input removed by compilation, between `0.0` and `1.0`.
+    pub fn reduction(&self) -> f64 {
+        if self.input_bytes == 0 {
+            return 0.0;
+        }
+        self.saved_bytes() as f64 / self.input_bytes as f64
+    }
+}
+
+/// Compiles the body of a `.osts` script down to the `fr` methods it uses.
+///
+/// # Errors
+///
+/// Returns a `CompileError` if the script has no `fr` namespace, the namespace can't be
+/// split into methods, or a required method is missing.
+pub fn compile(input: &str, options: &CompileOptions) -> Result<CompileOutput, CompileError> {
+    // Compiled scripts carry a header, which is replaced rather than kept in main
+    let (previous, body) = Header::strip(input);
+    let compiled = previous.is_some();
+    let header_lines = usize::from(compiled);
+    let mut source = FrostSource::from_body(body);
+    let original_main = source.main.clone();
+    let fr_lines = source.fr.matches('\n').count();
+    let fingerprint = previous.map_or_else(|| header::fingerprint(&source.fr), |previous| previous.fingerprint);
 
     // Extract all fr methods
-    let fr_namespace = source.extract_function_set();
+    let fr_namespace = extract(&mut source, options)?;
 
     // Build call graph
-    let fr_call_graph = super::compile::graph::Graph::from_function_set(&fr_namespace);
+    let mut fr_call_graph = graph::Graph::from_function_set_with(&fr_namespace, options.parser);
+    let defined: Vec<String> = fr_namespace
+        .dataframe_methods
+        .keys()
+        .map(|name| method_key(name))
+        .chain(fr_namespace.namespace_items.keys().map(|name| namespace_key(name)))
+        .chain(fr_namespace.member_keys())
+        .collect();
+
+    // Directives are read before comments are stripped from main
+    let mut keep_list = options.keep_list.clone();
+    keep_list.extend(KeepList::from_directives(&original_main));
+    let mut warnings = Vec::new();
+    let (keep, drop) = resolve_keep_list(&keep_list, &defined, compiled, &mut warnings);
+
+    // Dropped symbols leave the graph before it's searched, so code only they use goes too
+    fr_call_graph.remove_vertices(&drop);
 
     // Parse main body and track which fr methods are used
-    let mut parser = FunctionParser::new();
+    let mut parser = options.parser.analyzer();
+    parser.set_callback_types(&fr_namespace.callback_types());
     parser.parse(&source.main, "fr");
 
-    let mut called_functions = parser.get_methods();
-    called_functions.sort();
+    // Uses in main come first, so explanations point at the user's code where they can.
+    // The constructor is always kept, as is code like class fields that can reference
+    // namespace items too
+    let header = fr_namespace.header(&HashSet::new());
+    let call_sites = parser.get_call_sites();
+    let mut origins: IndexMap<String, Origin> = IndexMap::new();
+    for root in graph::call_targets(parser.as_ref())
+        .into_iter()
+        .chain(graph::member_references(&source.main, &fr_namespace))
+    {
+        let site = call_sites.get(&root).copied();
+        let location = explain::locate(&original_main, &source.main, site, &root, source.fr_line, fr_lines);
+        let origin = Origin::Main {
+            line: location.as_ref().map(|(line, _)| line + header_lines),
+            code: location.map(|(_, code)| code).unwrap_or_default(),
+        };
+        origins.entry(root).or_insert(origin);
+    }
+    // Kept symbols are searched like uses in main. Methods of other classes also need
+    // their class, or the search would never reach them
+    for root in keep {
+        if let Some(gate) = fr_call_graph.gates.get(&root) {
+            origins.entry(gate.clone()).or_insert(Origin::KeepList);
+        }
+        origins.entry(root).or_insert(Origin::KeepList);
+    }
+    origins.entry(method_key("constructor")).or_insert(Origin::AlwaysKept);
+    for root in graph::namespace_references(&header, &fr_namespace)
+        .into_iter()
+        .chain(graph::member_references(&header, &fr_namespace))
+    {
+        origins.entry(root).or_insert(Origin::Namespace);
+    }
+    let roots: Vec<String> = origins.keys().cloned().collect();
 
-    // BFS to resolve all dependent methods
-    let mut required_methods = fr_call_graph.search(&called_functions);
-    required_methods.insert("constructor".to_string());
+    // BFS to resolve all dependent methods, remembering how each was reached
+    let tree = fr_call_graph.search_tree(&roots);
+    let explanations = explain::explain(&tree, &origins);
+    let required_methods: HashSet<String> = tree.into_keys().collect();
 
     // Compile fr namespace down to only used methods
-    let compiled_fr_code = fr_namespace.compile(&required_methods);
+    let compiled_fr_code = fr_namespace
+        .compile(&required_methods)
+        .map_err(|e| locate_unknown_method(e, &explanations))?;
+
+    // Reattach the main code, either cleaned or exactly as written
+    let main = if options.preserve_main { &original_main } else { &source.main };
+    let header = Header::new(fingerprint.clone(), &required_methods.iter().cloned().collect());
+    let code = format!("{}\n{}\n{}", header, compiled_fr_code, main);
+    if options.verify {
+        warnings.extend(verify::verify(&code, &fr_namespace, &drop, options)?);
+    }
+
+    let dropped = defined.into_iter().filter(|name| !required_methods.contains(name)).collect();
+
+    let stats = SizeStats {
+        input_bytes: input.len(),
+        output_bytes: code.len(),
+    };
+
+    Ok(CompileOutput {
+        code,
+        fingerprint,
+        required: required_methods.into_iter().collect(),
+        dropped,
+        explanations,
+        stats,
+        warnings,
+        graph: fr_call_graph,
+    })
+}
+
+/// Points an `UnknownMethod` error at the use in main the method was reached from, found
+/// through its explanation. Other errors are returned as they are.
+fn locate_unknown_method(error: CompileError, explanations: &BTreeMap<String, Explanation>) -> CompileError {
+    match error {
+        CompileError::UnknownMethod { name, file, line: None } => {
+            let line = match explanations.get(&name).map(|explanation| &explanation.origin) {
+                Some(Origin::Main { line, .. }) => *line,
+                _ => None,
+            };
+            CompileError::UnknownMethod { name, file, line }
+        }
+        error => error,
+    }
+}
+
+/// Resolves the names in a keep list to symbols, returning the symbols to keep and to
+/// drop. Unknown names, names in both lists and attempts to drop the DataFrame
+/// constructor are skipped with a warning. Names dropped from an already compiled
+/// script are gone from its namespace, so they aren't reported as unknown.
+fn resolve_keep_list(
+    list: &KeepList,
+    defined: &[String],
+    compiled: bool,
+    warnings: &mut Vec<String>,
+) -> (Vec<String>, HashSet<String>) {
+    let mut keep = Vec::new();
+    for name in &list.keep {
+        match keep::resolve(name, defined) {
+            Some(symbol) => keep.push(symbol),
+            None => warnings.push(format!("`{}` in the keep list is not defined in the fr namespace", name)),
+        }
+    }
+
+    let mut drop = HashSet::new();
+    for name in &list.drop {
+        match keep::resolve(name, defined) {
+            Some(symbol) if keep.contains(&symbol) => {
+                warnings.push(format!("`{}` is in both the keep and drop lists, so it was kept", name));
+            }
+            Some(symbol) if symbol == method_key("constructor") => {
+                warnings.push("the DataFrame constructor is always kept and can't be dropped".to_string());
+            }
+            Some(symbol) => {
+                drop.insert(symbol);
+            }
+            None if compiled => {}
+            None => warnings.push(format!("`{}` in the drop list is not defined in the fr namespace", name)),
+        }
+    }
+    (keep, drop)
+}
+
+/// Builds the call graph of the `fr` namespace embedded in a script, like the one
+/// `compile` searches but with every symbol, dropped ones included. See
+/// `CompileOutput::graph` for the graph a compilation searched.
+///
+/// # Errors
+///
+/// Returns a `CompileError` if the script has no `fr` namespace or the namespace can't be
+/// split into methods.
+pub fn call_graph(input: &str, options: &CompileOptions) -> Result<graph::Graph, CompileError> {
+    let mut source = FrostSource::from_body(input);
+    let fr_namespace = extract(&mut source, options)?;
+    Ok(graph::Graph::from_function_set_with(&fr_namespace, options.parser))
+}
+
+/// Analyzes the structure of the `fr` namespace in a script or a bare library like
+/// `frosts.ts`: its cycles, dead helpers, fan-in and fan-out, and the cost of each
+/// public method.
+///
+/// # Errors
+///
+/// Returns a `CompileError` if there is no `fr` namespace or the namespace can't be
+/// split into methods.
+pub fn analyze_lib(input: &str, options: &CompileOptions) -> Result<graph::analysis::LibraryReport, CompileError> {
+    let mut source = FrostSource::from_body(input);
+    let fr_namespace = extract(&mut source, options)?;
+    let fr_call_graph = graph::Graph::from_function_set_with(&fr_namespace, options.parser);
+    Ok(graph::analysis::analyze(&fr_call_graph, &fr_namespace))
+}
+
+/// Preprocesses a script and extracts the functions and methods of its `fr` namespace.
+fn extract(source: &mut FrostSource, options: &CompileOptions) -> Result<FrostFunctionSet, CompileError> {
+    if source.fr.trim().is_empty() {
+        return Err(CompileError::MissingNamespace { file: None });
+    }
+
+    // Preprocess source and remove comments. Main is always cleaned for analysis,
+    // so commented-out code never counts as usage
+    source.preprocess(true, options.keep_license_comments);
+    source.extract_function_set()
+}
+
+/// Compiles a script with the default options, returning only the compiled code.
+pub fn compile_from_string(input: &str) -> Result<String, CompileError> {
+    compile(input, &CompileOptions::default()).map(|output| output.code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = r#"
+namespace fr {
+    export class DataFrame {
+        constructor(data: string[][]) {
+        }
+        head(n: number = 10): DataFrame {
+            const output = this.copy();
+            return output;
+        }
+        copy(): DataFrame {
+            return this;
+        }
+        tail(n: number = 10): DataFrame {
+            return this;
+        }
+    }
+}
+function main(workbook: ExcelScript.Workbook) {
+    let df = fr.read_sheet(workbook.getActiveWorksheet());
+    df.head();
+}
+"#;
+
+    #[test]
+    fn compile_reports_required_and_dropped_methods() {
+        let output = compile(SCRIPT, &CompileOptions::default()).unwrap();
+
+        assert!(output.required.contains("fr::DataFrame::head"));
+        assert!(output.required.contains("fr::DataFrame::copy"));
+        assert!(output.dropped.contains("fr::DataFrame::tail"));
+        assert!(!output.code.contains("tail("));
+    }
+
+    #[test]
+    fn compile_explains_why_symbols_were_kept() {
+        let output = compile(SCRIPT, &CompileOptions::default()).unwrap();
+
+        assert_eq!(output.explanations.keys().collect::<BTreeSet<_>>(), output.required.iter().collect());
+        assert_eq!(
+            output.explanations["fr::DataFrame::copy"].to_string(),
+            "main:line 20 df.head() -> head -> copy"
+        );
+        assert_eq!(output.explanations["fr::DataFrame::constructor"].origin, Origin::AlwaysKept);
+    }
+
+    #[test]
+    fn compile_explains_from_the_call_on_a_dataframe() {
+        // `list.head()` comes first, but only `df.head()` calls the DataFrame method
+        let script = SCRIPT.replace("    df.head();\n", "    let list = { head: () => 1 };\n    list.head();\n    df.head();\n");
+        for parser in [ParserBackend::Legacy, ParserBackend::Ast] {
+            let output = compile(&script, &CompileOptions { parser, ..Default::default() }).unwrap();
+            assert_eq!(
+                output.explanations["fr::DataFrame::copy"].to_string(),
+                "main:line 22 df.head() -> head -> copy"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_methods_are_reported_where_main_needs_them() {
+        let explanations = BTreeMap::from([(
+            "fr::DataFrame::missing".to_string(),
+            Explanation {
+                origin: Origin::Main { line: Some(20), code: "df.head()".to_string() },
+                chain: vec!["fr::DataFrame::head".to_string(), "fr::DataFrame::missing".to_string()],
+            },
+        )]);
+        let unknown = |name: &str| CompileError::UnknownMethod { name: name.to_string(), file: None, line: None };
+
+        let err = locate_unknown_method(unknown("fr::DataFrame::missing"), &explanations);
+        assert_eq!(err.to_string(), "line 20: method `fr::DataFrame::missing` is not defined in the `fr` namespace");
+        let err = locate_unknown_method(unknown("fr::other"), &explanations);
+        assert!(matches!(err, CompileError::UnknownMethod { line: None, .. }));
+    }
+
+    #[test]
+    fn compile_reports_size_stats() {
+        let output = compile(SCRIPT, &CompileOptions::default()).unwrap();
+
+        assert_eq!(output.stats.input_bytes, SCRIPT.len());
+        assert_eq!(output.stats.output_bytes, output.code.len());
+        assert!(output.stats.reduction() > 0.0);
+    }
+
+    #[test]
+    fn preserve_main_keeps_user_code_intact() {
+        let script = SCRIPT.replace(
+            "    df.head();\n",
+            "    // Only the first rows matter here\n\n\n    df.head();   \n",
+        );
+        let main = &script[script.find("function main").unwrap()..];
+
+        let options = CompileOptions { preserve_main: true, ..Default::default() };
+        let output = compile(&script, &options).unwrap();
+        assert!(output.code.ends_with(main));
+
+        let cleaned = compile(&script, &CompileOptions::default()).unwrap();
+        assert!(!cleaned.code.contains("Only the first rows"));
+    }
+
+    #[test]
+    fn preserved_comments_do_not_count_as_usage() {
+        let script = SCRIPT.replace("    df.head();\n", "    df.head();\n    // df.tail();\n");
+
+        let options = CompileOptions { preserve_main: true, ..Default::default() };
+        let output = compile(&script, &options).unwrap();
+        assert!(output.dropped.contains("fr::DataFrame::tail"));
+    }
+
+    #[test]
+    fn compile_is_deterministic() {
+        let content = crate::osts_reader::read_file("intro.osts").unwrap();
+        let script = crate::Osts::from_string(&content).unwrap();
+
+        let first = compile(&script.body, &CompileOptions::default()).unwrap();
+        for _ in 0..5 {
+            let again = compile(&script.body, &CompileOptions::default()).unwrap();
+            assert_eq!(first.code, again.code);
+        }
+    }
+
+    #[test]
+    fn keep_directives_add_roots() {
+        let script = SCRIPT.replace("    df.head();\n", "    // permafrost-keep: tail\n    df[method]();\n");
+        let output = compile(&script, &CompileOptions::default()).unwrap();
+
+        assert!(output.required.contains("fr::DataFrame::tail"));
+        assert!(!output.required.contains("fr::DataFrame::head"));
+        assert_eq!(output.explanations["fr::DataFrame::tail"].origin, Origin::KeepList);
+        assert!(output.warnings.is_empty());
+
+        // The directive survives comment stripping, so recompiling keeps `tail` too
+        assert!(output.code.contains("// permafrost-keep: tail"));
+        assert_eq!(compile(&output.code, &CompileOptions::default()).unwrap().code, output.code);
+    }
+
+    #[test]
+    fn drop_list_removes_used_symbols() {
+        let keep_list = KeepList { keep: Vec::new(), drop: vec!["copy".to_string()] };
+        let options = CompileOptions { keep_list, ..Default::default() };
+        let output = compile(SCRIPT, &options).unwrap();
+
+        assert!(output.required.contains("fr::DataFrame::head"));
+        assert!(output.dropped.contains("fr::DataFrame::copy"));
+        assert!(!output.explanations.contains_key("fr::DataFrame::copy"));
+        assert!(!output.code.contains("copy(): DataFrame"));
+        assert!(!output.graph.adj_list.contains_key("fr::DataFrame::copy"));
+        assert!(output.required.iter().all(|symbol| output.graph.adj_list.contains_key(symbol)));
+        assert_eq!(output.warnings, ["`copy` was dropped, but line 7 of the compiled output still uses it"]);
+    }
+
+    #[test]
+    fn dropped_symbols_take_their_callees_with_them() {
+        let script = SCRIPT.replace("    df.head();\n", "    df.head();\n    df.tail();\n");
+        let keep_list = KeepList { keep: Vec::new(), drop: vec!["head".to_string()] };
+        let options = CompileOptions { keep_list, ..Default::default() };
+        let output = compile(&script, &options).unwrap();
+
+        assert!(output.required.contains("fr::DataFrame::tail"));
+        assert!(output.dropped.contains("fr::DataFrame::head"));
+        assert!(output.dropped.contains("fr::DataFrame::copy"));
+        assert_eq!(output.warnings, ["`head` was dropped, but line 14 of the compiled output still uses it"]);
+    }
+
+    #[test]
+    fn keep_list_problems_are_warnings() {
+        let keep_list = KeepList {
+            keep: vec!["tail".to_string(), "melt".to_string()],
+            drop: vec!["fr::DataFrame::tail".to_string(), "constructor".to_string(), "pivot".to_string()],
+        };
+        let options = CompileOptions { keep_list, ..Default::default() };
+        let output = compile(SCRIPT, &options).unwrap();
+
+        assert!(output.required.contains("fr::DataFrame::tail"));
+        assert!(output.required.contains("fr::DataFrame::constructor"));
+        assert_eq!(
+            output.warnings,
+            [
+                "`melt` in the keep list is not defined in the fr namespace",
+                "`fr::DataFrame::tail` is in both the keep and drop lists, so it was kept",
+                "the DataFrame constructor is always kept and can't be dropped",
+                "`pivot` in the drop list is not defined in the fr namespace",
+            ]
+        );
+    }
+
+    /// Statements generated scripts are built from, each using a different part of frosts
+    const STATEMENTS: &[&str] = &[
+        "    df.describe().to_worksheet(sheet, 'o');",
+        "    df.melt(\"Field\", \"Value\", \"Salary\").head(5);",
+        "    let groups = df.groupBy([\"City\"], {\"Salary\": [\"mean\"]});",
+        "    df.filter(\"City\", fr.not(fr.predicates.equal(\"Chicago\")));",
+        "    let joined = fr.combine_dfs([df, df.copy()], \"inner\");",
+        "    df.query(row => row.get_number(\"Age\") > 30);",
+        "    console.log(fr.toJsDate(fr.today));",
+        "    df.pivot(\"City\", \"Department\", \"Salary\", \"sum\");",
+    ];
+
+    /// Builds pseudo-random scripts on the frosts namespace from intro.osts, choosing
+    /// statements with a fixed-seed xorshift so failures reproduce.
+    fn generated_scripts(count: usize) -> Vec<String> {
+        let content = crate::osts_reader::read_file("intro.osts").unwrap();
+        let script = crate::Osts::from_string(&content).unwrap();
+        let fr = FrostSource::from_body(&script.body).fr;
+
+        let mut state: u32 = 0x2545_f491;
+        (0..count)
+            .map(|_| {
+                let mut main = String::from("function main(workbook: ExcelScript.Workbook) {\n");
+                main.push_str("    let sheet = workbook.getActiveWorksheet();\n    let df = fr.read_sheet(sheet);\n");
+                for statement in STATEMENTS {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    if state.is_multiple_of(3) {
+                        main.push_str(statement);
+                        main.push('\n');
+                    }
+                }
+                main.push_str("}\n");
+                format!("{}\n{}", fr, main)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recompiling_output_changes_nothing() {
+        for (i, script) in generated_scripts(12).iter().enumerate() {
+            let options = CompileOptions { preserve_main: i % 2 == 0, ..Default::default() };
+            let once = compile(script, &options).unwrap();
+            let twice = compile(&once.code, &options).unwrap();
+
+            assert_eq!(once.code, twice.code, "script {} changed when recompiled:\n{}", i, script);
+            assert_eq!(once.required, twice.required);
+            assert_eq!(once.fingerprint, twice.fingerprint);
+        }
+    }
+
+    #[test]
+    fn compiled_output_starts_with_a_header() {
+        let script = &generated_scripts(1)[0];
+        let output = compile(script, &CompileOptions::default()).unwrap();
+        let header = Header::parse(&output.code).unwrap();
+
+        assert_eq!(header.fingerprint, header::fingerprint(&FrostSource::from_body(script).fr));
+        assert_eq!(header, Header::new(output.fingerprint.clone(), &output.required));
+
+        // The header of a compiled script is replaced, not carried into main
+        let again = compile(&output.code, &CompileOptions { preserve_main: true, ..Default::default() }).unwrap();
+        assert_eq!(again.code.matches(header::MARKER).count(), 1);
+        assert_eq!(again.fingerprint, output.fingerprint);
+    }
+
+    #[cfg(feature = "ast")]
+    #[test]
+    fn ast_and_legacy_backends_agree_on_intro() {
+        let content = crate::osts_reader::read_file("intro.osts").unwrap();
+        let script = crate::Osts::from_string(&content).unwrap();
+
+        let legacy = compile(&script.body, &CompileOptions::default()).unwrap();
+        let options = CompileOptions { parser: ParserBackend::Ast, ..Default::default() };
+        let ast = compile(&script.body, &options).unwrap();
+        assert_eq!(legacy.required, ast.required);
+    }
+
+    #[test]
+    fn compile_shakes_unused_namespace_functions() {
+        let script = r#"
+namespace fr {
+    function parse_cell(v) {
+        return v;
+    }
+    export function read_json(json: string): DataFrame {
+        return new DataFrame(JSON.parse(json));
+    }
+    export function combine_dfs(dfs: DataFrame[]): DataFrame {
+        let base_df = dfs[0];
+        return base_df.concat_all(...dfs.slice(1));
+    }
+    export class DataFrame {
+        values: Row[];
+        constructor(data) {
+            this.values = data.map(parse_cell);
+        }
+        concat_all(...others: DataFrame[]): DataFrame {
+            return this;
+        }
+    }
+}
+function main(workbook: ExcelScript.Workbook) {
+    let df = fr.read_json("[]");
+}
+"#;
+        let output = compile(script, &CompileOptions::default()).unwrap();
+
+        assert!(output.code.contains("function read_json"));
+        assert!(output.code.contains("function parse_cell"));
+        assert!(!output.code.contains("function combine_dfs"));
+        assert!(output.dropped.contains("fr::combine_dfs"));
+        assert!(output.dropped.contains("fr::DataFrame::concat_all"));
+
+        let script = script.replace("fr.read_json(\"[]\")", "fr.combine_dfs([])");
+        let output = compile(&script, &CompileOptions::default()).unwrap();
+        assert!(output.required.contains("fr::combine_dfs"));
+        assert!(output.required.contains("fr::DataFrame::concat_all"));
+        assert!(!output.code.contains("function read_json"));
+    }
+
+    #[test]
+    fn compile_keeps_methods_called_on_helper_results() {
+        let script = r#"
+namespace fr {
+    export function read_json(json: string): DataFrame {
+        return new DataFrame(JSON.parse(json));
+    }
+    export class DataFrame {
+        constructor(data) {}
+        merge(other: DataFrame): DataFrame {
+            return this;
+        }
+        melt(): DataFrame {
+            return this;
+        }
+    }
+}
+function main(workbook: ExcelScript.Workbook) {
+    let df = loadSales("[]");
+    df.merge(df);
+}
+function loadSales(json: string): fr.DataFrame {
+    return fr.read_json(json);
+}
+"#;
+        let output = compile(script, &CompileOptions::default()).unwrap();
+
+        assert!(output.required.contains("fr::DataFrame::merge"));
+        assert!(output.dropped.contains("fr::DataFrame::melt"));
+    }
+
+    /// A library with types, an interface used by callbacks, an object literal and a
+    /// second class, for tests that shake each of them.
+    const LIBRARY: &str = r#"
+namespace fr {
+    export type CellValue = string | number | boolean;
+    export type Operation = "sum" | "mean";
+    export type Row = { [key: string]: CellValue };
+    export interface FrostRow {
+        get_number(key: string): number;
+    }
+    function toFrostRow(row: Row): FrostRow {
+        return { get_number: (key) => Number(row[key]) };
+    }
+    type BooleanPredicate = (v: CellValue) => boolean;
+    export const predicates = {
+        is_blank: (v: CellValue) => v == "",
+        is_nan: (v: CellValue) => isNaN(Number(v)),
+        equal: (target: CellValue): BooleanPredicate => (v) => v == target
+    };
+    export const not = (predicate: BooleanPredicate): BooleanPredicate => (v) => !predicate(v);
+    export class Series {
+        values: CellValue[];
+        constructor(values: CellValue[]) {
+            this.values = values;
+        }
+        sum(): number {
+            return this.values.length;
+        }
+        mean(): number {
+            return this.sum() / this.values.length;
+        }
+    }
+    export class DataFrame {
+        constructor(data) {}
+        query(condition: (row: FrostRow) => boolean): DataFrame {
+            return new DataFrame(this.values.filter(row => condition(toFrostRow(row))));
+        }
+        column(name: string): Series {
+            return new Series([]);
+        }
+        head(): DataFrame {
+            return this;
+        }
+    }
+}
+"#;
+
+    /// Builds a script on `LIBRARY` whose main runs `main` on a DataFrame named `df`.
+    fn script(main: &str) -> String {
+        format!(
+            "{}function main(workbook: ExcelScript.Workbook) {{\n    let df = fr.read_sheet(workbook);\n    {}\n}}\n",
+            LIBRARY, main
+        )
+    }
+
+    #[test]
+    fn compile_keeps_frost_row_only_for_row_callbacks() {
+        let output = compile(&script("df.head();"), &CompileOptions::default()).unwrap();
+        assert!(output.dropped.contains("fr::FrostRow"));
+        assert!(output.dropped.contains("fr::toFrostRow"));
+        assert!(!output.code.contains("interface FrostRow"));
+
+        let output = compile(&script(r#"df.query(row => row.get_number("Age") > 18);"#), &CompileOptions::default()).unwrap();
+        assert!(output.required.contains("fr::FrostRow"));
+        assert!(output.required.contains("fr::toFrostRow"));
+        assert!(!output.required.contains("fr::DataFrame::get_number"));
+        assert!(output.code.contains("interface FrostRow"));
+    }
+
+    #[test]
+    fn compile_keeps_only_used_object_members() {
+        let output = compile(&script("let blank = fr.not(fr.predicates.is_blank);"), &CompileOptions::default()).unwrap();
+        assert!(output.required.contains("fr::predicates::is_blank"));
+        assert!(output.dropped.contains("fr::predicates::is_nan"));
+        assert!(output.dropped.contains("fr::predicates::equal"));
+        assert!(output.code.contains("is_blank: (v: CellValue)"));
+        assert!(!output.code.contains("is_nan:"));
+        assert!(!output.code.contains("equal:"));
+
+        let output = compile(&script("let all = Object.values(fr.predicates);"), &CompileOptions::default()).unwrap();
+        assert!(output.required.contains("fr::predicates::is_nan"));
+        assert!(output.code.contains("equal:"));
+    }
+
+    #[test]
+    fn compile_shakes_types_and_other_classes() {
+        let output = compile(&script("df.head();"), &CompileOptions::default()).unwrap();
+        assert!(output.dropped.contains("fr::Operation"));
+        assert!(output.dropped.contains("fr::CellValue"));
+        assert!(output.dropped.contains("fr::Series"));
+        assert!(!output.code.contains("class Series"));
+        assert!(!output.code.contains("type Operation"));
 
-    // Reattach the cleaned main code
-    let compiled_code = compiled_fr_code + "\n" + &source.main;
+        let output = compile(&script("let total = df.column(\"Age\").mean();"), &CompileOptions::default()).unwrap();
+        assert!(output.required.contains("fr::Series::mean"));
+        assert!(output.required.contains("fr::Series::sum"));
+        assert!(output.required.contains("fr::Series::constructor"));
+        assert!(output.required.contains("fr::CellValue"));
+        assert!(output.dropped.contains("fr::Operation"));
+        assert!(output.dropped.contains("fr::DataFrame::head"));
+        assert!(output.code.contains("values: CellValue[];"));
+    }
 
-    Ok(compiled_code)
+    #[test]
+    fn compile_without_namespace_fails() {
+        let err = compile("function main() {}", &CompileOptions::default()).unwrap_err();
+        assert!(matches!(err, CompileError::MissingNamespace { .. }));
+    }
 }
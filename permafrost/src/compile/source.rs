@@ -4,9 +4,14 @@
 //! split into its `namespace fr` body and `main` body. It also handles preprocessing
 //! and extraction of Frosts functions and methods from the `fr` namespace.
 
-use std::collections::{HashMap,HashSet};
-use crate::compile::utils::preprocess_code;
-use super::utils::clean_node;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use indexmap::IndexMap;
+use crate::compile::utils::{collapse_blank_lines, preprocess_code, preprocess_code_with};
+use super::error::CompileError;
+use super::code_parser::{CallbackParam, CallbackTypes, NAMESPACE};
+use super::lexer::{code_tokens, matching_close, split_list, split_statements, Token, TokenKind};
+use super::graph::utils::{member_key, method_key, namespace_key, strip_generics};
 /// Represents the two sections of a .osts script:
 /// - `fr`: the core library namespace
 /// - `main`: the user-facing entry point function
@@ -16,19 +21,45 @@ pub struct FrostSource {
     pub fr: String,
     /// The remaining code (typically the `main()` function)
     pub main: String,
+    /// 1-based line in the original body where `namespace fr` starts (0 if absent)
+    pub fr_line: usize,
 }
 
 /// Stores categorized function data extracted from the `fr` namespace:
-/// - top-level utility functions held in always_take
-/// - methods on `class DataFrame`
+/// - the whole namespace, held in always_take
+/// - top-level functions, variables, classes, interfaces and type aliases that can be
+///   shaken out of always_take
+/// - the members of object literals like `fr.predicates`, which are shaken one by one
+/// - methods on `class DataFrame`, and on any other class in the namespace
+///
+/// Unused code is cut out of always_take by its recorded byte range rather than
+/// reassembled, so the output keeps the source order and compiling the same input always
+/// produces byte-identical output.
 #[derive(Debug)]
 pub struct FrostFunctionSet {
-    /// Top-level `fr.functionName` mappings
+    /// The whole namespace, from `namespace fr {` to its closing brace
     pub always_take: String,
-    pub dataframe_methods: HashMap<String, String>,
-    pub problematic_methods: HashMap<String, String>
+    /// Top-level `fr.name` declarations, keyed by name. Each value is the exact text of
+    /// the declaration within always_take, which is cut out when it's unused
+    pub namespace_items: IndexMap<String, String>,
+    /// Members of `const` object literal items like `predicates`, keyed by item and then
+    /// member name. Each value is the exact text of the member within its item, including
+    /// its trailing comma, which is cut out when it's unused
+    pub object_members: IndexMap<String, IndexMap<String, String>>,
+    /// Methods of classes other than DataFrame, like a `Series`, keyed by class and then
+    /// method name. Fields stay with their class
+    pub class_members: IndexMap<String, IndexMap<String, String>>,
+    /// Methods of `class DataFrame`, including its constructor, getters, setters and
+    /// static methods
+    pub dataframe_methods: IndexMap<String, String>,
+    /// Byte range in always_take of every namespace item, member and method above, keyed
+    /// by vertex. Code is cut by range, so members with identical text can't be mixed up
+    pub spans: HashMap<String, Range<usize>>,
 }
 
+/// The class whose methods are found by tracking DataFrame values through the code.
+pub const DATAFRAME: &str = "DataFrame";
+
 impl FrostSource {
     /// Splits a raw `body` string into the `fr` namespace and the `main` logic.
     ///
@@ -36,36 +67,28 @@ impl FrostSource {
     ///
     /// * `body` - The full contents of a .osts file
     pub fn from_body(body: &str) -> Self {
-        let mut fr_namespace = String::new();
-        let mut main_script = String::new();
-
-        let mut inside_fr = false;
-        let mut brace_count = 0;
-
-        for line in body.lines() {
-            if line.contains("namespace fr") {
-                inside_fr = true;
-            }
-
-            if inside_fr {
-                fr_namespace.push_str(line);
-                fr_namespace.push('\n');
+        let tokens = code_tokens(body);
+        let namespace = tokens
+            .windows(3)
+            .position(|w| w[0].is("namespace") && w[1].is("fr") && w[2].is("{"));
 
-                brace_count += line.matches('{').count();
-                brace_count -= line.matches('}').count();
+        let Some(i) = namespace else {
+            return Self {
+                fr: String::new(),
+                main: body.to_string(),
+                fr_line: 0,
+            };
+        };
 
-                if brace_count == 0 {
-                    inside_fr = false;
-                }
-            } else {
-                main_script.push_str(line);
-                main_script.push('\n');
-            }
-        }
+        // Take whole lines, keeping line endings so main can be reproduced byte-for-byte
+        let start = line_start(body, tokens[i].start);
+        let end = matching_close(&tokens, i + 2)
+            .map_or(body.len(), |close| line_end(body, tokens[close].end()));
 
         Self {
-            fr: fr_namespace,
-            main: main_script,
+            fr: body[start..end].to_string(),
+            main: format!("{}{}", &body[..start], &body[end..]),
+            fr_line: tokens[i].line,
         }
     }
 
@@ -89,8 +112,9 @@ impl FrostSource {
     /// # Arguments
     ///
     /// * `clean_main` - If true, also cleans the main function body
-    pub fn preprocess(&mut self, clean_main: bool) {
-        self.fr = preprocess_code(&self.fr);
+    /// * `keep_license_comments` - If true, keeps `/*! ... */` comments inside the `fr` namespace
+    pub fn preprocess(&mut self, clean_main: bool, keep_license_comments: bool) {
+        self.fr = preprocess_code_with(&self.fr, keep_license_comments);
         if clean_main {
             self.main = preprocess_code(&self.main);
         }
@@ -101,132 +125,528 @@ impl FrostSource {
     /// # Returns
     ///
     /// A `FrostFunctionSet` containing:
-    /// - top-level functions
-    /// - DataFrame methods
-    /// - explicitly exported symbols
-    pub fn extract_function_set(&self) -> FrostFunctionSet {
-        let mut dataframe_methods = HashMap::new();
-        let mut problematic_methods = HashMap::new();
-
-        let mut current_fn = String::new();
-        let mut current_name = String::new();
-        let mut brace_depth: usize = 0;
-        let mut capturing = false;
-
-        let mut split_source = self.fr.split("constructor");
-        let before_class = split_source.next().unwrap().to_string();
-        let methods = "constructor".to_string() + split_source.next().unwrap();
-
-        // 1️⃣ Handle problematic methods (before the class)
-        for line in before_class.lines() {
-            let trimmed = line.trim();
-
-            if !capturing && trimmed.starts_with("export function") && trimmed.contains("combine_dfs(") {
-                current_name = "combine_dfs".to_string();
-                capturing = true;
-                brace_depth = 1;
-                current_fn = format!("{}\n", line);
+    /// - top-level declarations, including classes, interfaces and type aliases
+    /// - the members of object literals
+    /// - the methods of every class, with DataFrame's kept apart
+    ///
+    /// # Errors
+    ///
+    /// Returns `CompileError::MissingConstructor` if the namespace has no `DataFrame` class
+    /// with a constructor.
+    pub fn extract_function_set(&self) -> Result<FrostFunctionSet, CompileError> {
+        let fr = self.fr.as_str();
+        let tokens = code_tokens(fr);
+        let missing_constructor = || CompileError::MissingConstructor {
+            file: None,
+            line: self.fr_line.max(1),
+        };
+
+        let (lo, hi) = namespace_body(&tokens);
+        let mut namespace_items = IndexMap::new();
+        let mut object_members = IndexMap::new();
+        let mut class_members = IndexMap::new();
+        let mut dataframe_methods = None;
+        let mut spans = HashMap::new();
+        let mut previous_end = lo.checked_sub(1).map_or(0, |open| tokens[open].end());
+
+        // 1️⃣ Collect top-level declarations, classes included
+        for (start, end) in split_statements(&tokens, lo, hi) {
+            let code_start = member_code_start(fr, tokens[start].start, previous_end);
+            let code_end = statement_code_end(fr, &tokens, end);
+            previous_end = code_end;
+
+            let Some((keyword, name)) = declared_name(&tokens[start..end]) else {
                 continue;
-            }
+            };
+            namespace_items.insert(name.to_string(), fr[code_start..code_end].to_string());
+            spans.insert(namespace_key(name), code_start..code_end);
 
-            if capturing {
-                current_fn.push_str(line);
-                current_fn.push('\n');
-                brace_depth += line.matches('{').count();
-                brace_depth = brace_depth.saturating_sub(line.matches('}').count());
-
-                if brace_depth == 0 {
-                    problematic_methods.insert(current_name.clone(), current_fn.clone());
-                    capturing = false;
-                    current_fn.clear();
-                    current_name.clear();
+            // 2️⃣ Split classes into methods and object literals into members
+            if keyword == "class" {
+                let Some(open) = (start..end).find(|&i| tokens[i].is("{")) else {
+                    continue;
+                };
+                let close = matching_close(&tokens, open).unwrap_or(end);
+                let methods = class_methods(fr, &tokens, open, close);
+                let key = |method: &str| if name == DATAFRAME { method_key(method) } else { member_key(name, method) };
+                spans.extend(methods.iter().map(|(method, span)| (key(method), span.clone())));
+                let methods = texts(fr, methods);
+                if name == DATAFRAME {
+                    dataframe_methods = Some(methods);
+                } else {
+                    class_members.insert(name.to_string(), methods);
                 }
+            } else if let Some(members) = object_members_of(fr, &tokens, start, end) {
+                spans.extend(members.iter().map(|(member, span)| (member_key(name, member), span.clone())));
+                object_members.insert(name.to_string(), texts(fr, members));
             }
         }
 
-        // 2️⃣ Handle DataFrame methods
-        for line in methods.lines() {
-            let trimmed = line.trim();
-
-            if !capturing && trimmed.contains('(') && trimmed.ends_with('{') {
-                current_name = trimmed
-                    .split('(')
-                    .next()
-                    .unwrap_or("")
-                    .trim()
-                    .to_string();
-                capturing = true;
-                brace_depth = 1;
-                current_fn = format!("{}\n", line);
-                continue;
-            }
+        let dataframe_methods = dataframe_methods
+            .filter(|methods| methods.contains_key("constructor"))
+            .ok_or_else(missing_constructor)?;
+
+        Ok(FrostFunctionSet {
+            always_take: fr.to_string(),
+            namespace_items,
+            object_members,
+            class_members,
+            dataframe_methods,
+            spans,
+        })
+    }
+
+}
+
+/// Looks up the text of each span in `src`.
+fn texts(src: &str, spans: IndexMap<String, Range<usize>>) -> IndexMap<String, String> {
+    spans.into_iter().map(|(name, span)| (name, src[span].to_string())).collect()
+}
 
-            if capturing {
-                current_fn.push_str(line);
-                current_fn.push('\n');
-                brace_depth += line.matches('{').count();
-                brace_depth = brace_depth.saturating_sub(line.matches('}').count());
-
-                if brace_depth == 0 {
-                    let cleaned = clean_node(&current_name);
-                    dataframe_methods.insert(cleaned, current_fn.clone());
-                    capturing = false;
-                    current_fn.clear();
-                    current_name.clear();
+/// Returns `src` with every range in `cuts` removed. `src` starts at byte `offset` of the
+/// text the ranges index into, and ranges nested in an earlier one are skipped.
+fn cut(src: &str, offset: usize, mut cuts: Vec<Range<usize>>) -> String {
+    cuts.sort_by_key(|range| (range.start, std::cmp::Reverse(range.end)));
+    let mut output = String::with_capacity(src.len());
+    let mut position = offset;
+    for range in cuts {
+        if range.start < position {
+            continue;
+        }
+        output.push_str(&src[position - offset..range.start - offset]);
+        position = range.end;
+    }
+    output.push_str(&src[position - offset..]);
+    output
+}
+
+/// Byte offset of the start of the line containing `offset`.
+fn line_start(src: &str, offset: usize) -> usize {
+    src[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// Byte offset just past the line break ending the line containing `offset`.
+fn line_end(src: &str, offset: usize) -> usize {
+    src[offset..].find('\n').map_or(src.len(), |i| offset + i + 1)
+}
+
+/// End of the source text owned by the statement ending at token `end`: the rest of its
+/// line, unless another statement follows on the same line.
+fn statement_code_end(src: &str, tokens: &[Token], end: usize) -> usize {
+    let last = tokens[end - 1].end();
+    match tokens.get(end) {
+        Some(next) if !next.newline_before => last,
+        _ => line_end(src, last),
+    }
+}
+
+/// Returns the token range inside `namespace fr { ... }`, or every token if the
+/// source is a bare namespace body.
+fn namespace_body(tokens: &[Token]) -> (usize, usize) {
+    let open = tokens
+        .windows(3)
+        .position(|w| w[0].is("namespace") && w[1].is("fr") && w[2].is("{"))
+        .map(|i| i + 2);
+
+    match open.and_then(|open| Some((open, matching_close(tokens, open)?))) {
+        Some((open, close)) => (open + 1, close),
+        None => (0, tokens.len()),
+    }
+}
+
+/// Keywords that start a top-level declaration which can be shaken out by name.
+const DECLARATION_KEYWORDS: [&str; 9] = ["function", "const", "let", "var", "class", "interface", "type", "enum", "namespace"];
+
+/// Returns the keyword and name of a top-level declaration, like `("class", "DataFrame")`
+/// or `("type", "Row")`. Other statements (destructuring, bare expressions) return `None`.
+fn declared_name<'a>(statement: &[Token<'a>]) -> Option<(&'a str, &'a str)> {
+    let mut i = 0;
+    while statement
+        .get(i)
+        .is_some_and(|t| t.is("export") || t.is("declare") || t.is("async") || t.is("abstract"))
+    {
+        i += 1;
+    }
+
+    let keyword = statement.get(i)?;
+    if !DECLARATION_KEYWORDS.contains(&keyword.text) || keyword.kind != TokenKind::Ident {
+        return None;
+    }
+
+    // Skip the `*` of generator functions
+    let name = statement[i + 1..].iter().find(|t| !t.is("*"))?;
+    if name.kind != TokenKind::Ident {
+        return None;
+    }
+    // `type` is only a keyword when it declares an alias like `type Row = ...`
+    let after = statement.get(i + 2);
+    if keyword.is("type") && !after.is_some_and(|t| t.is("=") || t.is("<")) {
+        return None;
+    }
+    Some((keyword.text, name.text))
+}
+
+/// Returns the byte range of every method of the class whose body opens at `tokens[open]`,
+/// keyed by name, including its constructor, getters, setters and static methods. Fields stay
+/// with the class, and a getter and setter or overloads declared back to back are kept
+/// together under one name.
+fn class_methods(src: &str, tokens: &[Token], open: usize, close: usize) -> IndexMap<String, Range<usize>> {
+    let mut methods: IndexMap<String, (usize, usize)> = IndexMap::new();
+    let mut previous = None;
+    let mut previous_end = tokens[open].end();
+
+    for (start, end) in split_statements(tokens, open + 1, close) {
+        let code_start = member_code_start(src, tokens[start].start, previous_end);
+        let code_end = statement_code_end(src, tokens, end);
+        previous_end = code_end;
+
+        let name = method_name(&tokens[start..end]);
+        match name {
+            Some(name) if previous == Some(name) => {
+                if let Some(span) = methods.get_mut(name) {
+                    span.1 = code_end;
                 }
             }
+            // A second, separate declaration of a name stays with the class like a field
+            Some(name) if !methods.contains_key(name) => {
+                methods.insert(name.to_string(), (code_start, code_end));
+            }
+            _ => {}
         }
+        previous = name;
+    }
 
-        FrostFunctionSet {
-            always_take: before_class,
-            dataframe_methods,
-            problematic_methods,
+    methods.into_iter().map(|(name, (start, end))| (name, start..end)).collect()
+}
+
+/// If the statement `tokens[start..end]` declares a `const` object literal, like
+/// `export const predicates = { ... };`, returns the byte range of each of its members keyed by
+/// name, including the comments above it, its trailing comma and any blank lines after it. Objects with members that
+/// can't be shaken on their own, like spreads or computed keys, return `None`.
+fn object_members_of(src: &str, tokens: &[Token], start: usize, end: usize) -> Option<IndexMap<String, Range<usize>>> {
+    let keyword = (start..end).find(|&i| !tokens[i].is("export") && !tokens[i].is("declare"))?;
+    if !tokens[keyword].is("const") {
+        return None;
+    }
+    let open = (keyword..end).find(|&i| tokens[i].is("="))? + 1;
+    if !tokens.get(open)?.is("{") {
+        return None;
+    }
+    let close = matching_close(tokens, open)?;
+    if !(close + 1 == end || (close + 2 == end && tokens[close + 1].is(";"))) {
+        return None;
+    }
+
+    let mut members = IndexMap::new();
+    let mut previous_end = tokens[open].end();
+    for (lo, hi) in split_list(tokens, open + 1, close, false) {
+        let key = &tokens[lo];
+        let is_named = matches!(key.kind, TokenKind::Ident | TokenKind::String)
+            && (hi == lo + 1 || tokens[lo + 1].is(":") || tokens[lo + 1].is("("));
+        if !is_named {
+            return None;
         }
+        let name = key.text.trim_matches(|c| c == '"' || c == '\'');
+
+        // Each member owns the comma after it, so cutting it out leaves a valid literal
+        let owned_end = if hi < close { hi + 1 } else { hi };
+        let code_start = member_code_start(src, key.start, previous_end);
+        let mut code_end = statement_code_end(src, tokens, owned_end);
+        // Blank lines left behind by stripped doc comments go with the member above them
+        let next_line = line_start(src, tokens[owned_end].start);
+        if next_line > code_end && src[code_end..next_line].trim().is_empty() {
+            code_end = next_line;
+        }
+        previous_end = code_end;
+        members.insert(name.to_string(), code_start..code_end);
     }
+    (!members.is_empty()).then_some(members)
+}
 
+/// Keywords that may precede a class member's name.
+const MEMBER_MODIFIERS: [&str; 11] = [
+    "public", "private", "protected", "static", "readonly", "async", "abstract", "override",
+    "declare", "get", "set",
+];
+
+/// Returns the modifiers in front of a class member's name, like `["static", "async"]`.
+/// `get(key)` is a method called get, while `get shape()` is a getter named shape.
+fn member_modifiers<'a>(member: &[Token<'a>]) -> Vec<&'a str> {
+    member
+        .windows(2)
+        .take_while(|pair| MEMBER_MODIFIERS.contains(&pair[0].text) && (pair[1].kind == TokenKind::Ident || pair[1].is("*")))
+        .map(|pair| pair[0].text)
+        .collect()
 }
 
+/// Returns the name of the class member in `member` if it is a method (including the
+/// constructor, accessors, overload signatures and optional `name?()` methods), skipping
+/// modifiers, a generator's `*` and generic parameters. Returns `None` for fields.
+fn method_name<'a>(member: &[Token<'a>]) -> Option<&'a str> {
+    let mut i = member_modifiers(member).len();
+    if member.get(i).is_some_and(|t| t.is("*")) {
+        i += 1;
+    }
+
+    let name = member.get(i).filter(|t| t.kind == TokenKind::Ident)?;
+    i += 1;
+    if member.get(i).is_some_and(|t| t.is("?")) {
+        i += 1;
+    }
 
-impl FrostFunctionSet{
-    pub fn compile(&self, necessary_functions: &HashSet<String>) -> String {
-        let mut cleaned_header = self.always_take.clone();
-
-        // Remove all problematic methods that are NOT used
-        for (name, body) in &self.problematic_methods {
-            if !necessary_functions.contains(name) {
-                // naive removal: just slice out the function body
-                cleaned_header = cleaned_header.replace(body, "");
+    if member.get(i).is_some_and(|t| t.is("<")) {
+        let mut depth = 0usize;
+        while let Some(tok) = member.get(i) {
+            if tok.is("<") {
+                depth += 1;
+            } else if tok.is(">") {
+                depth -= 1;
+                if depth == 0 {
+                    i += 1;
+                    break;
+                }
             }
+            i += 1;
         }
+    }
+
+    member.get(i).filter(|t| t.is("(")).map(|_| name.text)
+}
+
+/// Returns true if `code` declares an interface, like `export interface FrostRow { ... }`.
+fn is_interface(code: &str) -> bool {
+    code_tokens(code)
+        .iter()
+        .find(|t| !t.is("export") && !t.is("declare"))
+        .is_some_and(|t| t.is("interface"))
+}
+
+/// Returns the parameter types of the callback declared by the parameter in `tokens[lo..hi]`,
+/// like `[Interface("FrostRow")]` for `condition: (row: FrostRow) => boolean`. Parameters
+/// that aren't inline function types return no types.
+fn callback_params(tokens: &[Token], lo: usize, hi: usize, interfaces: &HashSet<&str>) -> Vec<CallbackParam> {
+    let Some(colon) = (lo..hi).find(|&i| tokens[i].is(":")) else {
+        return Vec::new();
+    };
+    let open = colon + 1;
+    let is_function_type = tokens.get(open).is_some_and(|t| t.is("("))
+        && matching_close(tokens, open).is_some_and(|close| tokens.get(close + 1).is_some_and(|t| t.is("=>")));
+    if !is_function_type {
+        return Vec::new();
+    }
+
+    let close = matching_close(tokens, open).unwrap_or(hi);
+    split_list(tokens, open + 1, close, true)
+        .into_iter()
+        .map(|(lo, hi)| {
+            let Some(colon) = (lo..hi).find(|&i| tokens[i].is(":")) else {
+                return CallbackParam::Other;
+            };
+            // `fr.FrostRow` and `FrostRow` name the same type
+            let mut ty = &tokens[colon + 1..hi];
+            if ty.len() == 3 && ty[0].is(NAMESPACE) && ty[1].is(".") {
+                ty = &ty[2..];
+            }
+            match ty {
+                [name] if name.is("DataFrame") => CallbackParam::DataFrame,
+                [name] if interfaces.contains(name.text) => CallbackParam::Interface(name.text.to_string()),
+                _ => CallbackParam::Other,
+            }
+        })
+        .collect()
+}
 
-        // Handle apply<T> workaround
-        let method_str: String = necessary_functions
+/// Start of the source text owned by a class member: the start of its first line, extended
+/// upwards over comment lines (e.g. `/*! ... */` license blocks) that directly precede it.
+fn member_code_start(src: &str, member_start: usize, previous_end: usize) -> usize {
+    let mut start = line_start(src, member_start);
+    if start < previous_end {
+        return member_start;
+    }
+
+    while start > previous_end {
+        let above = line_start(src, start - 1);
+        let trimmed = src[above..start].trim();
+        if !(trimmed.starts_with("/*") || trimmed.starts_with('*')) {
+            break;
+        }
+        start = above;
+    }
+    start
+}
+
+impl FrostFunctionSet{
+    /// Emits the `fr` namespace reduced to the given set of required methods.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CompileError::UnknownMethod` if a required method isn't defined.
+    pub fn compile(&self, necessary_functions: &HashSet<String>) -> Result<String, CompileError> {
+        // Generic methods like apply<T> are keyed with their type parameters
+        let defined: HashSet<String> = self
+            .dataframe_methods
+            .keys()
+            .map(|name| method_key(name))
+            .chain(self.namespace_items.keys().map(|name| namespace_key(name)))
+            .chain(self.member_keys())
+            .collect();
+        let mut missing: Vec<&String> = necessary_functions
             .iter()
-            .map(|func| {
-                let call_method = if func == "apply" {
-                    "apply<T>"
-                } else {
-                    func
-                };
+            .filter(|func| !defined.contains(*func))
+            .collect();
+        missing.sort();
+        if let Some(func) = missing.first() {
+            return Err(CompileError::UnknownMethod {
+                name: func.to_string(),
+                file: None,
+                line: None,
+            });
+        }
+
+        let code = self.header(necessary_functions);
+        Ok(code.trim_end().to_string())
+    }
+
+    /// Returns the callback parameter types declared in each DataFrame method's signature,
+    /// like the `FrostRow` passed to `query(condition: (row: FrostRow) => boolean)`.
+    /// Methods that take no inline callbacks are left out.
+    pub fn callback_types(&self) -> CallbackTypes {
+        let interfaces: HashSet<&str> = self
+            .namespace_items
+            .iter()
+            .filter(|(_, code)| is_interface(code))
+            .map(|(name, _)| name.as_str())
+            .collect();
 
-                self.dataframe_methods
-                    .get(call_method)
-                    .expect(&format!("Couldn't find method: {:?}, continuing with compilation...", func))
+        self.dataframe_methods
+            .iter()
+            .filter_map(|(name, code)| {
+                let tokens = code_tokens(code);
+                let open = tokens.iter().position(|t| t.is("("))?;
+                let close = matching_close(&tokens, open)?;
+                let arguments: Vec<Vec<CallbackParam>> = split_list(&tokens, open + 1, close, true)
+                    .into_iter()
+                    .map(|(lo, hi)| callback_params(&tokens, lo, hi, &interfaces))
+                    .collect();
+                arguments.iter().any(|params| !params.is_empty()).then(|| (name.clone(), arguments))
             })
-            .cloned()
-            .collect::<Vec<String>>()
-            .join("\n");
+            .collect()
+    }
+
+    /// Returns always_take with every namespace item not in `necessary_functions` cut out.
+    /// Classes and object literals like `predicates` keep only their members in
+    /// `necessary_functions`, as do DataFrame methods given without their class.
+    pub fn header(&self, necessary_functions: &HashSet<String>) -> String {
+        let unused = self
+            .spans
+            .iter()
+            .filter(|(vertex, _)| !necessary_functions.contains(*vertex))
+            .map(|(_, span)| span.clone())
+            .collect();
+        collapse_blank_lines(&cut(&self.always_take, 0, unused))
+    }
+
+    /// Returns the members of the namespace item `name` that are shaken one by one, with
+    /// their vertices: the methods of a class or the properties of an object literal.
+    pub fn members_of(&self, name: &str) -> Vec<(String, &String)> {
+        if name == DATAFRAME {
+            return self.dataframe_methods.iter().map(|(method, code)| (method_key(method), code)).collect();
+        }
+        self.class_members
+            .get(name)
+            .or_else(|| self.object_members.get(name))
+            .map(|members| members.iter().map(|(member, code)| (member_key(name, member), code)).collect())
+            .unwrap_or_default()
+    }
 
-        // Combine everything into a full script
-        vec![
-            cleaned_header,
-            method_str,
-            String::from("}"),
-            String::from("}"),
-        ]
-        .join("\n")
+    /// Returns the text of the namespace item `name` without its members, like a class's
+    /// header and fields.
+    pub fn shell(&self, name: &str) -> String {
+        let Some(span) = self.spans.get(&namespace_key(name)) else {
+            return self.namespace_items.get(name).cloned().unwrap_or_default();
+        };
+        let members = self
+            .members_of(name)
+            .into_iter()
+            .filter_map(|(vertex, _)| self.spans.get(&vertex).cloned())
+            .collect();
+        cut(&self.always_take[span.clone()], span.start, members)
+    }
+
+    /// Returns the vertex of every object literal member and method of a class other than
+    /// DataFrame, like `fr::predicates::is_blank` or `fr::Series::sum`.
+    pub fn member_keys(&self) -> impl Iterator<Item = String> + '_ {
+        self.object_members
+            .iter()
+            .chain(&self.class_members)
+            .flat_map(|(item, members)| members.keys().map(move |member| member_key(item, member)))
+    }
+
+    /// Returns every vertex with the code it adds to the output when kept: the shell of
+    /// namespace items that have members, and the text of everything else.
+    pub fn symbols(&self) -> IndexMap<String, String> {
+        let mut symbols = IndexMap::new();
+        for name in self.namespace_items.keys() {
+            symbols.insert(namespace_key(name), self.shell(name));
+            for (vertex, code) in self.members_of(name) {
+                symbols.insert(vertex, code.clone());
+            }
+        }
+        // Methods given without their class
+        for (name, code) in &self.dataframe_methods {
+            symbols.entry(method_key(name)).or_insert_with(|| code.clone());
+        }
+        symbols
     }
 
+    /// Returns true if the vertex is an interface or type alias, like `fr::FrostRow` or
+    /// `fr::Row`. They're kept for the names they declare rather than for code they run.
+    pub fn is_type(&self, vertex: &str) -> bool {
+        let Some(item) = vertex.strip_prefix("fr::").filter(|item| !item.contains("::")) else {
+            return false;
+        };
+        self.namespace_items.get(item).is_some_and(|code| {
+            let tokens = code_tokens(code);
+            declared_name(&tokens).is_some_and(|(keyword, _)| keyword == "interface" || keyword == "type")
+        })
+    }
+
+    /// Returns true if the vertex can be used from outside the namespace: exported items,
+    /// the members of exported object literals, and class members that aren't `private`,
+    /// `protected` or named like a `__helper`.
+    pub fn is_public(&self, vertex: &str) -> bool {
+        let exported = |name: &str| {
+            self.namespace_items
+                .get(name)
+                .is_some_and(|code| code_tokens(code).first().is_some_and(|t| t.is("export")))
+        };
+        let mut parts = vertex.split("::").skip(1);
+        let (Some(item), member) = (parts.next(), parts.next()) else {
+            return false;
+        };
+        let Some(member) = member else {
+            return exported(item);
+        };
+
+        let code = if item == DATAFRAME {
+            self.dataframe_methods.iter().find(|(name, _)| strip_generics(name) == member).map(|(_, code)| code)
+        } else if let Some(members) = self.class_members.get(item) {
+            members.get(member)
+        } else {
+            return exported(item);
+        };
+        let hidden = code.is_some_and(|code| {
+            let tokens = code_tokens(code);
+            let declaration = split_statements(&tokens, 0, tokens.len())
+                .into_iter()
+                .map(|(start, end)| &tokens[start..end])
+                .find(|declaration| method_name(declaration).is_some());
+            declaration.is_some_and(|d| member_modifiers(d).iter().any(|m| *m == "private" || *m == "protected"))
+        });
+        // Methods given without their class count as exported
+        let class_exported = !self.namespace_items.contains_key(item) || exported(item);
+        class_exported && !hidden && !member.starts_with("__")
+    }
 }
 #[cfg(test)]
 mod tests {
@@ -313,7 +733,7 @@ mod tests {
         "#;
 
         let source = FrostSource::from_body(code);
-        let parsed = source.extract_function_set();
+        let parsed = source.extract_function_set().unwrap();
 
         assert!(parsed.dataframe_methods.contains_key("filter"));
         assert!(parsed.dataframe_methods.contains_key("rename"));
@@ -339,7 +759,7 @@ mod tests {
         "#;
 
         let source = FrostSource::from_body(code);
-        let parsed = source.extract_function_set();
+        let parsed = source.extract_function_set().unwrap();
 
         assert_eq!(parsed.dataframe_methods.len(), 2);
         assert!(parsed.dataframe_methods.contains_key("nested"));
@@ -347,8 +767,11 @@ mod tests {
     }
 
     #[test]
-    fn extract_function_set_moves_combine_dfs_to_problematic() {
+    fn extract_function_set_collects_namespace_items() {
         let src = r#"
+            let SEPARATOR = "~~~";
+            export const not = (predicate) => (v) => !predicate(v);
+            type Predicate = (v: CellValue) => boolean;
             export function combine_dfs(dfs: DataFrame[]): DataFrame {
                 return dfs[0].concat_all("outer", ...dfs.slice(1));
             }
@@ -364,33 +787,445 @@ mod tests {
             }
         "#;
 
-        let fake_source = FrostSource { fr: src.to_string(), main:String::from("") };
-        let frost_set = fake_source.extract_function_set();
+        let fake_source = FrostSource { fr: src.to_string(), main:String::from(""), fr_line: 1 };
+        let frost_set = fake_source.extract_function_set().unwrap();
 
-        assert!(frost_set.problematic_methods.contains_key("combine_dfs"));
+        let items: Vec<&String> = frost_set.namespace_items.keys().collect();
+        assert_eq!(items, ["SEPARATOR", "not", "Predicate", "combine_dfs", "DataFrame"]);
+        assert!(frost_set.namespace_items["combine_dfs"].trim_end().ends_with('}'));
         assert!(frost_set.dataframe_methods.contains_key("filter"));
         assert!(!frost_set.dataframe_methods.contains_key("combine_dfs"));
     }
 
+    /// Records the span of each symbol's text within `always_take`, like extraction does.
+    fn spans_of(always_take: &str, symbols: &[(&str, &str)]) -> HashMap<String, Range<usize>> {
+        symbols
+            .iter()
+            .map(|(vertex, code)| {
+                let start = always_take.find(code).unwrap();
+                (vertex.to_string(), start..start + code.len())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn extract_function_set_splits_object_literals_into_members() {
+        let src = r#"
+            export const predicates = {
+                is_blank: (v: CellValue) => v == "",
+                "is_nan": (v: CellValue) => isNaN(Number(v)),
+                equal(target: CellValue) {
+                    return (v) => v == target;
+                }
+            };
+            export const spread = { ...predicates, extra: 1 };
+            export let mutable = { a: 1 };
+            class DataFrame {
+                constructor(data) {}
+            }
+        "#;
+
+        let fake_source = FrostSource { fr: src.to_string(), main: String::new(), fr_line: 1 };
+        let frost_set = fake_source.extract_function_set().unwrap();
+        let members = &frost_set.object_members["predicates"];
+
+        assert_eq!(members.keys().collect::<Vec<_>>(), ["is_blank", "is_nan", "equal"]);
+        assert!(members["is_blank"].trim_end().ends_with("v == \"\","));
+        assert!(members["equal"].trim_end().ends_with('}'));
+        assert!(!frost_set.object_members.contains_key("spread"));
+        assert!(!frost_set.object_members.contains_key("mutable"));
+
+        let necessary = HashSet::from([namespace_key("predicates"), member_key("predicates", "equal")]);
+        let header = frost_set.header(&necessary);
+        assert!(header.contains("equal(target"));
+        assert!(!header.contains("is_blank"));
+        assert!(!header.contains("is_nan"));
+    }
+
+    #[test]
+    fn members_with_identical_text_are_cut_by_range() {
+        let src = "export const first = {\n    limit: 10,\n};\nexport const second = {\n    limit: 10,\n};\nclass DataFrame {\n    constructor(data) {}\n}\n";
+        let fake_source = FrostSource { fr: src.to_string(), main: String::new(), fr_line: 1 };
+        let frost_set = fake_source.extract_function_set().unwrap();
+
+        let necessary = HashSet::from([
+            namespace_key("first"),
+            namespace_key("second"),
+            member_key("second", "limit"),
+        ]);
+        let header = frost_set.header(&necessary);
+        assert!(header.starts_with("export const first = {\n};\nexport const second = {\n    limit: 10,\n};\n"));
+    }
+
+    #[test]
+    fn extract_function_set_splits_every_class() {
+        let src = r#"
+            export type Operation = "sum" | "mean";
+            export function make(): DataFrame {
+                return new DataFrame([]).constructor;
+            }
+            export class Series {
+                values: number[] = [];
+                constructor(values: number[]) {
+                    this.values = values;
+                }
+                get size(): number {
+                    return this.values.length;
+                }
+                set size(n: number) {
+                    this.values.length = n;
+                }
+                static of(...values: number[]): Series {
+                    return new Series(values);
+                }
+                sum(): number {
+                    return this.values.reduce((a, b) => a + b, 0);
+                }
+            }
+            export class DataFrame {
+                columns: string[];
+                constructor(data) {}
+                static from_rows(rows): DataFrame {
+                    return new DataFrame(rows);
+                }
+            }
+        "#;
+
+        let fake_source = FrostSource { fr: src.to_string(), main: String::new(), fr_line: 1 };
+        let frost_set = fake_source.extract_function_set().unwrap();
+
+        let items: Vec<&String> = frost_set.namespace_items.keys().collect();
+        assert_eq!(items, ["Operation", "make", "Series", "DataFrame"]);
+        let series = &frost_set.class_members["Series"];
+        assert_eq!(series.keys().collect::<Vec<_>>(), ["constructor", "size", "of", "sum"]);
+        assert!(series["size"].contains("get size()") && series["size"].contains("set size("));
+        assert_eq!(frost_set.dataframe_methods.keys().collect::<Vec<_>>(), ["constructor", "from_rows"]);
+
+        let shell = frost_set.shell("Series");
+        assert!(shell.contains("values: number[] = [];"));
+        assert!(!shell.contains("sum()"));
+
+        let necessary = HashSet::from([
+            namespace_key("Series"),
+            namespace_key("DataFrame"),
+            member_key("Series", "sum"),
+            method_key("constructor"),
+        ]);
+        let output = frost_set.compile(&necessary).unwrap();
+        assert!(output.contains("sum(): number"));
+        assert!(output.contains("values: number[] = [];"));
+        assert!(!output.contains("get size()"));
+        assert!(!output.contains("static of"));
+        assert!(!output.contains("from_rows"));
+        assert!(!output.contains("Operation"));
+    }
+
     #[test]
-    fn compile_does_not_include_unnecessary_problematic_methods() {
+    fn members_are_cut_by_range_not_by_text() {
+        // The object literal in the constructor has the same text as the unused method
+        let code = "namespace fr {\nexport class DataFrame {\nconstructor() {}\n}\nexport class Series {\nconstructor() {\nconst inner = {\n    total() { return 0; }\n};\n}\n    total() { return 0; }\n}\n}\n";
+        let frost_set = FrostSource::from_body(code).extract_function_set().unwrap();
+        let needed = ["fr::DataFrame", "fr::DataFrame::constructor", "fr::Series", "fr::Series::constructor"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let header = frost_set.header(&needed);
+
+        assert!(header.contains("const inner = {\n    total() { return 0; }\n};"));
+        assert_eq!(header.matches("total()").count(), 1);
+        assert!(!frost_set.shell("Series").contains("total()"));
+    }
+
+    #[test]
+    fn extracts_overloads_accessors_and_static_members() {
+        let src = r#"
+            class DataFrame {
+                readonly columns: string[];
+                static readonly VERSION = "1";
+
+                constructor(
+                    data: CellValue[][]
+                ) {
+                    this.columns = [];
+                }
+
+                get shape(): [number, number] {
+                    return [0, this.columns.length];
+                }
+                set shape(v: [number, number]) {
+                    this.columns.length = v[1];
+                }
+
+                head(): DataFrame
+                head(n: number): DataFrame;
+                head(n?: number): DataFrame {
+                    return this;
+                }
+
+                static from_rows(
+                    rows: CellValue[][],
+                    header: boolean
+                ):
+                    DataFrame {
+                    return new DataFrame(rows);
+                }
+
+                describe?(columns: string[]): DataFrame;
+
+                *rows() {
+                    yield* this.values;
+                }
+            }
+        "#;
+
+        let fake_source = FrostSource { fr: src.to_string(), main: String::new(), fr_line: 1 };
+        let frost_set = fake_source.extract_function_set().unwrap();
+        let methods = &frost_set.dataframe_methods;
+
+        assert_eq!(
+            methods.keys().collect::<Vec<_>>(),
+            ["constructor", "shape", "head", "from_rows", "describe", "rows"]
+        );
+        assert!(methods["shape"].contains("get shape()") && methods["shape"].contains("set shape("));
+        assert!(methods["describe"].trim_start().starts_with("describe?(columns: string[]): DataFrame;"));
+        assert!(methods["from_rows"].contains("rows: CellValue[][],") && methods["from_rows"].contains("new DataFrame(rows)"));
+        assert!(!methods["constructor"].contains("get shape"));
+
+        // Overload signatures go wherever the implementation does, and fields stay
+        let necessary = HashSet::from([namespace_key("DataFrame"), method_key("constructor"), method_key("head")]);
+        let output = frost_set.compile(&necessary).unwrap();
+        assert!(output.contains("head(): DataFrame\n"));
+        assert!(output.contains("head(n: number): DataFrame;"));
+        assert!(output.contains("head(n?: number): DataFrame {"));
+        assert!(output.contains("readonly columns: string[];"));
+        assert!(output.contains("static readonly VERSION"));
+        assert!(!output.contains("shape"));
+        assert!(!output.contains("from_rows"));
+    }
+
+    #[test]
+    fn callback_types_come_from_method_signatures() {
+        let src = r#"
+            export type Row = { [key: string]: CellValue };
+            export interface FrostRow {
+                get_number(key: string): number;
+            }
+            class DataFrame {
+                constructor(data) {}
+                query(condition: (row: FrostRow) => boolean): DataFrame {
+                    return this;
+                }
+                replace_column(columnName: string, fn: (value: CellValue, index?: number) => CellValue): DataFrame {
+                    return this;
+                }
+                merge_with(other: DataFrame, combine: (left: fr.DataFrame, right: Row) => DataFrame): DataFrame {
+                    return this;
+                }
+                head(n: number = 10): DataFrame {
+                    return this;
+                }
+            }
+        "#;
+
+        let fake_source = FrostSource { fr: src.to_string(), main: String::new(), fr_line: 1 };
+        let frost_set = fake_source.extract_function_set().unwrap();
+        let callbacks = frost_set.callback_types();
+
+        assert!(frost_set.namespace_items.contains_key("FrostRow"));
+        assert_eq!(callbacks["query"], [vec![CallbackParam::Interface("FrostRow".to_string())]]);
+        assert_eq!(callbacks["replace_column"], [vec![], vec![CallbackParam::Other, CallbackParam::Other]]);
+        assert_eq!(callbacks["merge_with"], [vec![], vec![CallbackParam::DataFrame, CallbackParam::Other]]);
+        assert!(!callbacks.contains_key("head"));
+    }
+
+    #[test]
+    fn compile_drops_unused_namespace_items() {
+        let always_take = "export function combine_dfs(...) {\n    return 'test';\n}\nlet unused = 1;\nfilter() { return this; }";
         let frost_set = FrostFunctionSet {
-            always_take: "export function combine_dfs(...) {\n    return 'test';\n}\nlet unused = 1;".to_string(),
+            always_take: always_take.to_string(),
             dataframe_methods: {
-                let mut hm = HashMap::new();
+                let mut hm = IndexMap::new();
                 hm.insert("filter".to_string(), "filter() { return this; }".to_string());
                 hm
             },
-            problematic_methods: {
-                let mut hm = HashMap::new();
+            object_members: IndexMap::new(),
+            class_members: IndexMap::new(),
+            namespace_items: {
+                let mut hm = IndexMap::new();
                 hm.insert("combine_dfs".to_string(), "export function combine_dfs(...) {\n    return 'test';\n}\n".to_string());
                 hm
             },
+            spans: spans_of(always_take, &[
+                ("fr::combine_dfs", "export function combine_dfs(...) {\n    return 'test';\n}\n"),
+                ("fr::DataFrame::filter", "filter() { return this; }"),
+            ]),
         };
 
-        let output = frost_set.compile(&["filter".to_string()].into_iter().collect());
+        let output = frost_set.compile(&["fr::DataFrame::filter".to_string()].into_iter().collect()).unwrap();
 
         assert!(!output.contains("combine_dfs"));
+        assert!(output.contains("let unused = 1;"));
         assert!(output.contains("filter()"));
+
+        let needed = ["fr::DataFrame::filter", "fr::combine_dfs"].iter().map(|s| s.to_string()).collect();
+        assert!(frost_set.compile(&needed).unwrap().contains("combine_dfs"));
+    }
+
+    #[test]
+    fn compile_resolves_generic_method_names() {
+        let frost_set = FrostFunctionSet {
+            always_take: "__apply_typed<T>() { }".to_string(),
+            dataframe_methods: IndexMap::from([
+                ("__apply_typed<T>".to_string(), "__apply_typed<T>() { }".to_string()),
+            ]),
+            namespace_items: IndexMap::new(),
+            object_members: IndexMap::new(),
+            class_members: IndexMap::new(),
+            spans: HashMap::from([(method_key("__apply_typed<T>"), 0..22)]),
+        };
+
+        let output = frost_set.compile(&["fr::DataFrame::__apply_typed".to_string()].into_iter().collect()).unwrap();
+        assert!(output.contains("__apply_typed<T>() { }"));
+    }
+
+    #[test]
+    fn compile_reports_unknown_method() {
+        let frost_set = FrostFunctionSet {
+            always_take: String::new(),
+            dataframe_methods: IndexMap::new(),
+            namespace_items: IndexMap::new(),
+            object_members: IndexMap::new(),
+            class_members: IndexMap::new(),
+            spans: HashMap::new(),
+        };
+
+        let err = frost_set.compile(&["melt".to_string()].into_iter().collect()).unwrap_err();
+        assert!(matches!(err, CompileError::UnknownMethod { ref name, .. } if name == "melt"));
+    }
+
+    #[test]
+    fn missing_constructor_is_an_error() {
+        let code = r#"
+            namespace fr {
+                export function helper() { return 1; }
+            }
+            function main() {}
+        "#;
+
+        let source = FrostSource::from_body(code);
+        let err = source.extract_function_set().unwrap_err();
+        assert!(matches!(err, CompileError::MissingConstructor { line: 2, .. }));
+    }
+
+    #[test]
+    fn extraction_preserves_source_order() {
+        let code = r#"
+            namespace fr {
+                export class DataFrame {
+                    constructor(){
+                    }
+                    zeta() {
+                        return this;
+                    }
+                    alpha() {
+                        return this;
+                    }
+                    mid() {
+                        return this;
+                    }
+                }
+            }
+        "#;
+
+        let parsed = FrostSource::from_body(code).extract_function_set().unwrap();
+        let names: Vec<&String> = parsed.dataframe_methods.keys().collect();
+        assert_eq!(names, ["constructor", "zeta", "alpha", "mid"]);
+    }
+
+    #[test]
+    fn compile_emits_methods_in_source_order() {
+        let frost_set = FrostFunctionSet {
+            always_take: "zeta() {}\nalpha() {}\nmid() {}".to_string(),
+            dataframe_methods: IndexMap::from([
+                ("zeta".to_string(), "zeta() {}".to_string()),
+                ("alpha".to_string(), "alpha() {}".to_string()),
+                ("mid".to_string(), "mid() {}".to_string()),
+            ]),
+            namespace_items: IndexMap::new(),
+            object_members: IndexMap::new(),
+            class_members: IndexMap::new(),
+            spans: spans_of("zeta() {}\nalpha() {}\nmid() {}", &[
+                ("fr::DataFrame::zeta", "zeta() {}"),
+                ("fr::DataFrame::alpha", "alpha() {}"),
+                ("fr::DataFrame::mid", "mid() {}"),
+            ]),
+        };
+
+        let needed = ["mid", "zeta", "alpha"].iter().map(|s| method_key(s)).collect();
+        let output = frost_set.compile(&needed).unwrap();
+        let zeta = output.find("zeta").unwrap();
+        let alpha = output.find("alpha").unwrap();
+        let mid = output.find("mid").unwrap();
+        assert!(zeta < alpha && alpha < mid);
+    }
+
+    #[test]
+    fn from_body_preserves_main_bytes() {
+        let body = "namespace fr {\r\n}\r\nfunction main() {\r\n    // keep me\r\n\r\n\r\n}";
+        let source = FrostSource::from_body(body);
+        assert_eq!(source.main, "function main() {\r\n    // keep me\r\n\r\n\r\n}");
+    }
+
+    #[test]
+    fn license_comment_travels_with_next_method() {
+        let code = r#"
+            namespace fr {
+                export class DataFrame {
+                    constructor(){
+                    }
+                    /*! Adapted from an MIT licensed snippet */
+                    melt() {
+                        return this;
+                    }
+                }
+            }
+        "#;
+
+        let mut source = FrostSource::from_body(code);
+        source.preprocess(true, true);
+        let parsed = source.extract_function_set().unwrap();
+        assert!(parsed.dataframe_methods["melt"].contains("/*! Adapted from an MIT licensed snippet */"));
+    }
+
+    #[test]
+    fn records_namespace_start_line() {
+        let source = FrostSource::from_body("\n\nnamespace fr {\n}\nfunction main() {}\n");
+        assert_eq!(source.fr_line, 3);
+    }
+
+    #[test]
+    fn extracts_one_line_and_multi_line_methods() {
+        let code = r#"
+            namespace fr {
+                export class DataFrame {
+                    columns: string[];
+                    constructor(){
+                    }
+                    is_empty(): boolean { return this.values.length == 0 }
+                    rename(
+                        mapping: { [key: string]: string }
+                    ): DataFrame {
+                        const braces = "}}";
+                        return this;
+                    }
+                }
+            }
+        "#;
+
+        let parsed = FrostSource::from_body(code).extract_function_set().unwrap();
+        let names: Vec<&String> = parsed.dataframe_methods.keys().collect();
+        assert_eq!(names, ["constructor", "is_empty", "rename"]);
+        assert!(parsed.dataframe_methods["rename"].trim_end().ends_with('}'));
+        assert!(parsed.always_take.contains("columns: string[];"));
     }
 }
@@ -4,9 +4,8 @@
 //! This module contains utility functions used throughout the Frostbite compiler,
 //! including code preview (for debugging) and preprocessing logic for cleaning Office Script code.
 
-use walkdir::{WalkDir, DirEntry};
-use std::path::PathBuf;
-use dirs;
+use super::keep::is_directive;
+use super::lexer::{tokenize, TokenKind};
 
 /// Prints a peek of the source code, showing the first and last `n_lines / 2`.
 /// Used for debugging transformations like splitting or stripping.
@@ -15,7 +14,6 @@ use dirs;
 ///
 /// * `source` - A string slice representing the source code to inspect.
 /// * `n_lines` - The total number of lines to show. If the code is shorter than this, the entire content is shown.
-
 pub fn peek_code(source: &str, n_lines: usize) {
     // Split source code into individual lines
     let fr_lines: Vec<&str> = source.lines().collect();
@@ -38,9 +36,9 @@ pub fn peek_code(source: &str, n_lines: usize) {
 /// This is an essential preprocessing step before attempting to parse or transform the code.
 ///
 /// Handles:
-/// - `//` single-line comments
+/// - `//` single-line comments, except `// permafrost-keep:` and `// permafrost-drop:` directives
 /// - `/* ... */` block comments
-/// - Preserves string literals (even multiline)
+/// - Preserves string, template and regex literals, using the shared `lexer`
 ///
 /// # Arguments
 ///
@@ -50,79 +48,32 @@ pub fn peek_code(source: &str, n_lines: usize) {
 ///
 /// A cleaned version of the code as a single `String`, with all comments and redundant whitespace removed.
 pub fn preprocess_code(code: &str) -> String {
-    let mut result = String::new();
-    let mut chars = code.chars().peekable();
-
-    let mut in_string = false;
-    let mut string_delim = '\0';
-    let mut in_single_comment = false;
-    let mut in_multi_comment = false;
-
-    while let Some(c) = chars.next() {
-        if !in_single_comment && !in_multi_comment {
-            if in_string {
-                // Handle string escaping
-                result.push(c);
-                if c == '\\' {
-                    if let Some(next) = chars.next() {
-                        result.push(next);
-                    }
-                    continue;
-                }
-                // Close string if end detected
-                if c == string_delim {
-                    in_string = false;
-                }
-                continue;
-            }
-
-            // Detect start of string
-            if c == '"' || c == '\'' || c == '`' {
-                in_string = true;
-                string_delim = c;
-                result.push(c);
-                continue;
-            }
+    preprocess_code_with(code, false)
+}
 
-            // Detect start of comment blocks
-            if c == '/' {
-                if let Some(&next) = chars.peek() {
-                    if next == '/' {
-                        in_single_comment = true;
-                        chars.next(); // Consume the second '/'
-                        continue;
-                    } else if next == '*' {
-                        in_multi_comment = true;
-                        chars.next(); // Consume the '*'
-                        continue;
-                    }
-                }
-            }
-        }
+/// Same as `preprocess_code`, but optionally keeps `/*! ... */` license-style comments intact.
+///
+/// # Arguments
+///
+/// * `code` - The raw source code to clean
+/// * `keep_license_comments` - If true, block comments opened with `/*!` are preserved verbatim
+pub fn preprocess_code_with(code: &str, keep_license_comments: bool) -> String {
+    let mut result = String::new();
 
-        // Newlines always terminate single-line comments
-        if c == '\n' {
-            in_single_comment = false;
-            if !in_multi_comment {
-                result.push('\n');
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::LineComment if is_directive(token.text) => result.push_str(token.text),
+            TokenKind::LineComment => {}
+            TokenKind::BlockComment if keep_license_comments && token.text.starts_with("/*!") => {
+                result.push_str(token.text);
             }
-            continue;
-        }
-
-        // Detect end of multi-line comment
-        if in_multi_comment && c == '*' {
-            if let Some(&next) = chars.peek() {
-                if next == '/' {
-                    in_multi_comment = false;
-                    chars.next(); // Consume '/'
+            TokenKind::BlockComment => {
+                // Keep neighbouring tokens apart, e.g. `let/* note */x`
+                if !result.is_empty() && !result.ends_with(char::is_whitespace) {
+                    result.push(' ');
                 }
             }
-            continue;
-        }
-
-        // Normal character
-        if !in_single_comment && !in_multi_comment {
-            result.push(c);
+            _ => result.push_str(token.text),
         }
     }
 
@@ -133,10 +84,14 @@ pub fn preprocess_code(code: &str) -> String {
         .collect::<Vec<_>>()
         .join("\n");
 
-    // Remove consecutive blank lines
+    collapse_blank_lines(&cleaned)
+}
+
+/// Collapses every run of blank lines in `code` into a single empty line.
+pub fn collapse_blank_lines(code: &str) -> String {
     let mut final_out = String::new();
     let mut last_blank = false;
-    for line in cleaned.lines() {
+    for line in code.lines() {
         if line.trim().is_empty() {
             if !last_blank {
                 final_out.push('\n');
@@ -158,43 +113,6 @@ pub fn clean_node(s: &str) -> String{
     s.strip_prefix("private").unwrap_or(s).trim().to_string()
 }
 
-pub fn find_files(targ: &str) -> Result<Vec<PathBuf>, String> {
-    let dirs_to_walk = vec![
-        Some(PathBuf::from(".")),
-        dirs::document_dir(),
-        dirs::download_dir(),
-    ];
-
-    let mut matches = vec![];
-
-    for maybe_dir in dirs_to_walk.into_iter().flatten() {
-        for entry in WalkDir::new(maybe_dir)
-            .into_iter()
-            .filter_map(Result::ok)
-            .filter(|e| is_osts(e) && e.file_name().to_str().unwrap_or("").ends_with(targ))
-        {
-            matches.push(entry.path().to_path_buf());
-        }
-    }
-
-    if matches.is_empty() {
-        Err(format!("Unable to find any file ending with `{}`", targ))
-    } else {
-        Ok(matches)
-    }
-}
-
-
-fn is_osts(entry: &DirEntry) -> bool {
-    entry.file_type().is_file() &&
-    entry.path()
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext_str| ext_str == "osts")
-        .unwrap_or(false)
-}
-
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -258,6 +176,34 @@ mod tests {
         assert!(out.contains(r#"let comment = "// not really";"#));
     }
 
+    #[test]
+    fn keeps_license_comments_when_requested() {
+        let input = "/*! frosts | MIT License */\n/* internal note */\nlet a = 1;\n";
+        let kept = preprocess_code_with(input, true);
+        assert!(kept.contains("/*! frosts | MIT License */"));
+        assert!(!kept.contains("internal note"));
+
+        let stripped = preprocess_code(input);
+        assert!(!stripped.contains("MIT License"));
+    }
+
+    #[test]
+    fn keeps_multiline_license_comments() {
+        let input = "/*!\n * Copyright frosts\n */\nlet a = 1; // gone\n";
+        let out = preprocess_code_with(input, true);
+        assert!(out.contains("/*!\n * Copyright frosts\n */"));
+        assert!(!out.contains("gone"));
+    }
+
+    #[test]
+    fn preserves_comment_markers_in_templates_and_regexes() {
+        let input = "let url = `http://${host}/*`;\nlet re = /\\/\\/+/g; // trailing\n";
+        let out = preprocess_code(input);
+        assert!(out.contains("`http://${host}/*`"));
+        assert!(out.contains("/\\/\\/+/g;"));
+        assert!(!out.contains("trailing"));
+    }
+
     #[test]
     fn clean_node_strips_private(){
         assert_eq!(
@@ -0,0 +1,230 @@
+//! # verify
+//!
+//! Checks a compiled script after tree shaking: every `this.x`, `fr.x`, bare namespace
+//! name and DataFrame method call it uses must still be defined in the emitted namespace.
+//! A miss means the call graph lost an edge, and the script would fail at runtime.
+//!
+//! References are found from tokens alone, independently of the parser backend that
+//! built the call graph, so a usage the parser missed is caught here rather than trusted.
+
+use std::collections::HashSet;
+use std::ops::Range;
+use super::error::CompileError;
+use super::explain::short_name;
+use super::graph::utils::{member_key, method_key, namespace_key, Vertex};
+use super::code_parser::{type_arguments_end, NAMESPACE};
+use super::lexer::{code_tokens, matching_close, Token, TokenKind};
+use super::source::{FrostFunctionSet, FrostSource, DATAFRAME};
+use super::{extract, CompileOptions};
+
+/// Methods of arrays, strings, maps and `Math` that DataFrame methods share a name with.
+/// A bare `.filter(` is far more likely an array's, so these only count on `this`.
+const BUILTIN_METHODS: &[&str] = &[
+    "concat", "filter", "map", "slice", "sort", "join", "includes", "indexOf", "keys", "values", "entries",
+    "forEach", "reduce", "find", "some", "every", "push", "pop", "fill", "flat", "at", "min", "max", "abs",
+    "round", "floor", "ceil", "toString", "split", "trim", "replace", "get", "set", "has", "delete", "clear",
+    "add",
+];
+
+/// Re-parses `code`, the output of compiling a script against `library`, and fails with
+/// `CompileError::UnresolvedReference` at the first use of a removed symbol. Symbols in
+/// `dropped` were removed on request, so their uses are returned as warnings instead.
+pub fn verify(
+    code: &str,
+    library: &FrostFunctionSet,
+    dropped: &HashSet<Vertex>,
+    options: &CompileOptions,
+) -> Result<Vec<String>, CompileError> {
+    let mut source = FrostSource::from_body(code);
+    let emitted = extract(&mut source, options)?;
+    let defined: HashSet<Vertex> = emitted.symbols().into_keys().collect();
+    let known: HashSet<Vertex> = library.symbols().into_keys().collect();
+
+    // Members of other classes only count once their class is kept, so `.sum` on an
+    // array doesn't need `Series.sum`
+    let is_missing = |vertex: &Vertex| {
+        let name = member_name(vertex);
+        let gated = library.class_members.iter().any(|(class, members)| {
+            members.contains_key(name) && member_key(class, name) == *vertex && !defined.contains(&namespace_key(class))
+        });
+        known.contains(vertex) && !defined.contains(vertex) && !gated
+    };
+
+    let tokens = code_tokens(code);
+    let namespace = namespace_range(&tokens);
+    let classes = class_ranges(&tokens, &namespace);
+    let mut warned = HashSet::new();
+    let mut warnings = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Ident {
+            continue;
+        }
+        let class = classes.iter().find(|(range, _)| range.contains(&i)).map(|(_, class)| *class);
+        for vertex in referenced(&tokens, i, library, &namespace, class).into_iter().filter(is_missing) {
+            if !dropped.contains(&vertex) {
+                return Err(CompileError::UnresolvedReference { name: short_name(&vertex), file: None, line: token.line });
+            }
+            if warned.insert(vertex.clone()) {
+                warnings.push(format!(
+                    "`{}` was dropped, but line {} of the compiled output still uses it",
+                    short_name(&vertex),
+                    token.line
+                ));
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+/// Returns the symbols of `library` that the identifier at `tokens[i]` may refer to:
+/// - `this.x`, the method `x` of the class it's in, or of DataFrame outside classes
+/// - `fr.x`, the namespace item `x`
+/// - `fr.x.y` and `DataFrame.y`, the member `y` of the object literal or class `x`
+/// - any other `.x(`, the DataFrame method `x` unless a built-in has the same name,
+///   and any `.x`, the members `x` of other classes
+/// - a bare `x` inside the namespace, the namespace item `x`
+fn referenced(
+    tokens: &[Token],
+    i: usize,
+    library: &FrostFunctionSet,
+    namespace: &Range<usize>,
+    class: Option<&str>,
+) -> Vec<Vertex> {
+    let name = tokens[i].text;
+    let before = |n: usize| i.checked_sub(n).map(|j| &tokens[j]);
+    let is_dot = |t: Option<&Token>| t.is_some_and(|t| t.is(".") || t.is("?."));
+    if !is_dot(before(1)) {
+        let is_item = namespace.contains(&i) && library.namespace_items.contains_key(name);
+        return if is_item { vec![namespace_key(name)] } else { Vec::new() };
+    }
+
+    // `fr.predicates` anywhere, or a bare `predicates` inside the namespace
+    let qualified_receiver = if is_dot(before(3)) {
+        before(4).is_some_and(|t| t.is(NAMESPACE)) && !is_dot(before(5))
+    } else {
+        namespace.contains(&i)
+    };
+    match before(2).filter(|t| t.kind == TokenKind::Ident).map(|t| t.text) {
+        Some("this") => match class {
+            Some(class) if class != DATAFRAME => vec![member_key(class, name)],
+            _ => vec![method_key(name)],
+        },
+        Some(NAMESPACE) if !is_dot(before(3)) => vec![namespace_key(name)],
+        Some(DATAFRAME) if qualified_receiver => vec![method_key(name)],
+        Some(item) if library.object_members.contains_key(item) || library.class_members.contains_key(item) => {
+            if qualified_receiver { vec![member_key(item, name)] } else { Vec::new() }
+        }
+        _ => {
+            let called = tokens.get(i + 1).is_some_and(|t| t.is("(")) || type_arguments_end(tokens, i + 1).is_some();
+            let method = (called && !BUILTIN_METHODS.contains(&name)).then(|| method_key(name));
+            let members = library
+                .class_members
+                .iter()
+                .filter(|(_, members)| members.contains_key(name))
+                .map(|(class, _)| member_key(class, name));
+            method.into_iter().chain(members).collect()
+        }
+    }
+}
+
+/// Returns the last `::` segment of a vertex, like `sum` for `fr::Series::sum`.
+fn member_name(vertex: &str) -> &str {
+    vertex.rsplit("::").next().unwrap_or(vertex)
+}
+
+/// Returns the token range of the body of `namespace fr { ... }`, empty if there is none.
+fn namespace_range(tokens: &[Token]) -> Range<usize> {
+    tokens
+        .windows(3)
+        .position(|w| w[0].is("namespace") && w[1].is(NAMESPACE) && w[2].is("{"))
+        .and_then(|i| Some(i + 3..matching_close(tokens, i + 2)?))
+        .unwrap_or(0..0)
+}
+
+/// Returns the token ranges of the class bodies in the namespace with their class names.
+fn class_ranges<'a>(tokens: &[Token<'a>], namespace: &Range<usize>) -> Vec<(Range<usize>, &'a str)> {
+    namespace
+        .clone()
+        .filter(|&i| tokens[i].is("class") && tokens.get(i + 1).is_some_and(|t| t.kind == TokenKind::Ident))
+        .filter_map(|i| {
+            let open = (i + 2..namespace.end).find(|&j| tokens[j].is("{"))?;
+            Some((open + 1..matching_close(tokens, open)?, tokens[i + 1].text))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY: &str = "namespace fr {
+    export function read_csv(text: string): DataFrame {
+        return new DataFrame(parse(text));
+    }
+    function parse(text: string): string[][] {
+        return [[text]];
+    }
+    export class DataFrame {
+        constructor(data: string[][]) {}
+        head(): DataFrame {
+            return this.copy();
+        }
+        copy(): DataFrame {
+            return this;
+        }
+    }
+}
+function main() {
+    let df = fr.read_csv('a');
+    df.head();
+}";
+
+    fn library() -> FrostFunctionSet {
+        let mut source = FrostSource::from_body(LIBRARY);
+        extract(&mut source, &CompileOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn complete_output_verifies() {
+        let options = CompileOptions::default();
+        let output = super::super::compile(LIBRARY, &options).unwrap();
+
+        assert!(verify(&output.code, &library(), &HashSet::new(), &options).is_ok());
+    }
+
+    #[test]
+    fn removed_methods_fail_at_their_first_use() {
+        let broken = LIBRARY.replace("        copy(): DataFrame {\n            return this;\n        }\n", "");
+        let err = verify(&broken, &library(), &HashSet::new(), &CompileOptions::default()).unwrap_err();
+
+        assert!(matches!(
+            err,
+            CompileError::UnresolvedReference { ref name, line: 11, .. } if name == "copy"
+        ));
+    }
+
+    #[test]
+    fn removed_namespace_items_fail_in_main_and_the_namespace() {
+        let broken = LIBRARY.replace("    function parse(text: string): string[][] {\n        return [[text]];\n    }\n", "");
+        let err = verify(&broken, &library(), &HashSet::new(), &CompileOptions::default()).unwrap_err();
+        assert!(matches!(err, CompileError::UnresolvedReference { ref name, line: 3, .. } if name == "fr.parse"));
+
+        let broken = LIBRARY.replace("    export function read_csv", "    function unused");
+        let err = verify(&broken, &library(), &HashSet::new(), &CompileOptions::default()).unwrap_err();
+        assert!(matches!(err, CompileError::UnresolvedReference { ref name, line: 19, .. } if name == "fr.read_csv"));
+    }
+
+    #[test]
+    fn references_are_found_without_the_parser() {
+        // `q` is only assigned after its declaration, and `filter` is an array's
+        let script = LIBRARY
+            .replace("    let df = fr.read_csv('a');\n    df.head();", "    let q;\n    q = fr.read_csv('a');\n    [1].filter(x => x);\n    q.copy();")
+            .replace("            return this.copy();", "            return this;");
+        let options = CompileOptions { verify: false, ..Default::default() };
+        let output = super::super::compile(&script, &options).unwrap();
+        let stripped = output.code.replace("        copy(): DataFrame {\n            return this;\n        }\n", "");
+        let err = verify(&stripped, &library(), &HashSet::new(), &options).unwrap_err();
+
+        assert!(matches!(err, CompileError::UnresolvedReference { ref name, .. } if name == "copy"));
+    }
+}
@@ -0,0 +1,52 @@
+//! # permafrost
+//!
+//! A tree-shaking compiler for [frosts](https://joeyrussoniello.github.io/frosts/) Office Scripts.
+//! Given a script that embeds the `fr` namespace, permafrost keeps only the functions
+//! and `DataFrame` methods reachable from the user's code.
+//!
+//! ```
+//! use permafrost::{compile, CompileOptions};
+//!
+//! let script = r#"
+//! namespace fr {
+//!     export class DataFrame {
+//!         constructor(data: string[][]) {
+//!         }
+//!         head(n: number = 10): DataFrame {
+//!             return this;
+//!         }
+//!         tail(n: number = 10): DataFrame {
+//!             return this;
+//!         }
+//!     }
+//! }
+//! function main(workbook: ExcelScript.Workbook) {
+//!     let df = new fr.DataFrame([["a"]]);
+//!     df.head();
+//! }
+//! "#;
+//!
+//! let output = compile(script, &CompileOptions::default()).unwrap();
+//! assert!(output.required.contains("fr::DataFrame::head"));
+//! assert!(output.dropped.contains("fr::DataFrame::tail"));
+//! ```
+//!
+//! The clipboard, file search and CLI used by the `permafrost` binary sit behind the
+//! `clipboard`, `search`, `interactive` and `cli` cargo features. The optional `ast`
+//! feature adds `AstParser`, which analyzes scripts with a full TypeScript parser.
+
+pub mod compile;
+pub mod osts_reader;
+
+#[cfg(feature = "search")]
+pub mod search;
+
+pub use compile::{analyze_lib, call_graph, compile, CompileOptions, CompileOutput, SizeStats};
+pub use compile::code_parser::{FunctionParser, ParserBackend, UsageAnalyzer};
+#[cfg(feature = "ast")]
+pub use compile::code_parser::ast::AstParser;
+pub use compile::error::CompileError;
+pub use compile::explain::Explanation;
+pub use compile::graph::Graph;
+pub use compile::source::{FrostFunctionSet, FrostSource};
+pub use osts_reader::Osts;
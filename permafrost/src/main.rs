@@ -1,28 +1,252 @@
-use std::io::{self, Write};
-use arboard::Clipboard;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::process::ExitCode;
+use clap::Parser;
 
-mod osts_reader;
-mod compile;
+mod cli;
 
-use compile::{compile_from_string, utils::find_files};
-use osts_reader::{read_file, Osts};
+use cli::{exit_code, AnalyzeLibArgs, Cli, Command, CompileArgs, ExplainArgs, GraphArgs};
+use permafrost::compile::keep::{self, KeepList};
+use permafrost::osts_reader::read_file;
+use permafrost::{analyze_lib, compile, CompileError, CompileOptions, CompileOutput, Osts, ParserBackend};
+
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            // `--help` and `--version` also arrive here, printed to stdout
+            return if e.use_stderr() { ExitCode::from(exit_code::USAGE) } else { ExitCode::from(exit_code::SUCCESS) };
+        }
+    };
+
+    match cli.command {
+        Some(Command::Compile(args)) => run_compile(&args),
+        Some(Command::Explain(args)) => run_explain(&args),
+        Some(Command::Graph(args)) => run_graph(&args),
+        Some(Command::AnalyzeLib(args)) => run_analyze_lib(&args),
+        None => run_interactive(),
+    }
+}
+
+/// Non-interactive compilation of a single script, driven by `permafrost compile`.
+fn run_compile(args: &CompileArgs) -> ExitCode {
+    if args.parser.backend == ParserBackend::Ast && !cfg!(feature = "ast") {
+        eprintln!("⚠️ permafrost was built without the `ast` feature, using the legacy parser");
+    }
+
+    let (script, compiled) = match load_and_compile(&args.path, &args.options()) {
+        Ok((script, output)) => (script, output.code),
+        Err(e) => return report_error(&e),
+    };
+
+    if args.osts {
+        let minified = script.with_body(compiled);
+        if args.stdout {
+            print!("{}", minified);
+            return ExitCode::from(exit_code::SUCCESS);
+        }
+        let Some(output) = args.osts_output() else {
+            eprintln!("❌ {} is already minified, pass -o to choose where to write the script", args.path.display());
+            return ExitCode::from(exit_code::USAGE);
+        };
+        return match minified.write_to_file(&output) {
+            Ok(()) => {
+                eprintln!("✅ Frostbite compilation complete. Output written to {}", output.display());
+                ExitCode::from(exit_code::SUCCESS)
+            }
+            Err(e) => report_error(&e),
+        };
+    }
+
+    if let Some(output) = &args.output {
+        return write_output(output, &compiled);
+    }
+
+    if args.stdout || !io::stdin().is_terminal() || !cfg!(feature = "clipboard") {
+        print!("{}", compiled);
+        return ExitCode::from(exit_code::SUCCESS);
+    }
+
+    match copy_to_clipboard(&compiled) {
+        Ok(()) => {
+            eprintln!("✅ Frostbite compilation complete. Output copied to clipboard.");
+            ExitCode::from(exit_code::SUCCESS)
+        }
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            ExitCode::from(exit_code::IO_ERROR)
+        }
+    }
+}
+
+/// Prints why each kept symbol is in the compiled script, driven by `permafrost explain`.
+fn run_explain(args: &ExplainArgs) -> ExitCode {
+    let output = match load_and_compile(&args.path, &args.parser.options()) {
+        Ok((_, output)) => output,
+        Err(e) => return report_error(&e),
+    };
+
+    let Some(symbol) = &args.symbol else {
+        for explanation in output.explanations.values() {
+            println!("{}", explanation);
+        }
+        return ExitCode::from(exit_code::SUCCESS);
+    };
+
+    // Resolved like names in the keep list, so `explain count` and `permafrost-keep: count`
+    // mean the same symbol
+    let Some(vertex) = keep::resolve(symbol, output.required.iter().chain(&output.dropped)) else {
+        eprintln!("❌ {} is not defined in the fr namespace", symbol);
+        return ExitCode::from(exit_code::COMPILE_ERROR);
+    };
+    match output.explanations.get(&vertex) {
+        Some(explanation) => {
+            println!("{}", explanation);
+            ExitCode::from(exit_code::SUCCESS)
+        }
+        None => {
+            eprintln!("❌ {} is not used, so it was removed from the compiled script", symbol);
+            ExitCode::from(exit_code::COMPILE_ERROR)
+        }
+    }
+}
+
+/// Prints the call graph of the script's namespace, driven by `permafrost graph`.
+fn run_graph(args: &GraphArgs) -> ExitCode {
+    // The graph compile searched, so dropped symbols are gone from it like from the script
+    let output = match load_and_compile(&args.path, &args.parser.options()) {
+        Ok((_, output)) => output,
+        Err(e) => return report_error(&e),
+    };
+
+    let reachable = output.required.into_iter().collect();
+    print!("{}", output.graph.render(args.format, &reachable));
+    ExitCode::from(exit_code::SUCCESS)
+}
+
+/// Prints the structural report of a frosts library, driven by `permafrost analyze-lib`.
+fn run_analyze_lib(args: &AnalyzeLibArgs) -> ExitCode {
+    let source = match read_file(&args.path) {
+        Ok(content) => content,
+        Err(e) => return report_error(&e),
+    };
+    // `.osts` scripts wrap the code in JSON, while libraries like `frosts.ts` are bare
+    let body = if args.path.extension().is_some_and(|ext| ext == "osts") {
+        match Osts::from_string(&source) {
+            Ok(script) => script.body,
+            Err(e) => return report_error(&e.with_file(&args.path)),
+        }
+    } else {
+        source
+    };
+
+    match analyze_lib(&body, &args.parser.options()) {
+        Ok(report) => {
+            print!("{}", report);
+            ExitCode::from(exit_code::SUCCESS)
+        }
+        Err(e) => report_error(&e.with_file(&args.path)),
+    }
+}
+
+/// The original prompt-driven flow, used when `permafrost` is launched without arguments.
+#[cfg(feature = "interactive")]
+fn run_interactive() -> ExitCode {
+    let interactive = io::stdin().is_terminal();
 
-fn main() {
     println!("❄️ Welcome to Permafrost!");
     println!("What frosts file would you like to condense?");
     println!("(We'll search in Documents, Downloads, and this directory)");
     println!("Note: The filename is case-sensitive and must include the `.osts` extension.\n");
 
-    let path = loop {
+    let Some(path) = prompt_for_path() else {
+        eprintln!("❌ No file selected.");
+        return ExitCode::from(exit_code::IO_ERROR);
+    };
+
+    println!("\n📄 Found script at: {}\nBeginning Compilation...\n", path.display());
+
+    let compiled = match load_and_compile(&path, &CompileOptions::default()) {
+        Ok((_, output)) => output.code,
+        Err(e) => {
+            let code = report_error(&e);
+            if interactive {
+                pause_terminal();
+            }
+            return code;
+        }
+    };
+
+    if !interactive {
+        print!("{}", compiled);
+        return ExitCode::from(exit_code::SUCCESS);
+    }
+
+    let code = match copy_to_clipboard(&compiled) {
+        Ok(()) => {
+            println!("✅ Frostbite compilation complete. Output copied to clipboard.");
+            exit_code::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            exit_code::IO_ERROR
+        }
+    };
+
+    pause_terminal();
+    ExitCode::from(code)
+}
+
+/// Without the `interactive` feature there is no prompt, so point the user at the subcommands.
+#[cfg(not(feature = "interactive"))]
+fn run_interactive() -> ExitCode {
+    use clap::CommandFactory;
+    let _ = Cli::command().print_help();
+    ExitCode::from(exit_code::USAGE)
+}
+
+/// Reads the `.osts` script at `path` and compiles its body, keeping and dropping the
+/// symbols listed in a `permafrost.toml` next to it. Warnings are printed to stderr.
+fn load_and_compile(path: &Path, options: &CompileOptions) -> Result<(Osts, CompileOutput), CompileError> {
+    let content = read_file(path)?;
+    let script = Osts::from_string(&content).map_err(|e| e.with_file(path))?;
+    let mut options = options.clone();
+    options.keep_list = KeepList::for_script(path)?;
+    let output = compile(&script.body, &options).map_err(|e| e.with_file(path))?;
+    for warning in &output.warnings {
+        eprintln!("⚠️ {}: {}", path.display(), warning);
+    }
+    Ok((script, output))
+}
+
+/// Prints a compilation error and maps it to the matching process exit code.
+fn report_error(error: &CompileError) -> ExitCode {
+    eprintln!("❌ Compilation failed: {}", error);
+    if error.is_io() {
+        ExitCode::from(exit_code::IO_ERROR)
+    } else {
+        ExitCode::from(exit_code::COMPILE_ERROR)
+    }
+}
+
+/// Repeatedly asks for a file name until exactly one matching `.osts` file is chosen.
+/// Returns `None` once stdin is exhausted.
+#[cfg(feature = "interactive")]
+fn prompt_for_path() -> Option<std::path::PathBuf> {
+    use std::io::Write;
+    use permafrost::search::find_files;
+
+    loop {
         print!("Enter file name (e.g., `frosts.osts`): ");
         io::stdout().flush().unwrap();
 
-        let mut filename = String::new();
-        io::stdin().read_line(&mut filename).unwrap();
+        let filename = read_line()?;
         let filename = filename.trim();
 
         match find_files(filename) {
-            Ok(paths) if paths.len() == 1 => break paths[0].clone(),
+            Ok(paths) if paths.len() == 1 => return Some(paths[0].clone()),
             Ok(paths) if paths.len() > 1 => {
                 println!("\n🔎 Found multiple matching files:");
                 for (i, path) in paths.iter().enumerate() {
@@ -31,12 +255,11 @@ fn main() {
                 print!("\nEnter number of the file to use: ");
                 io::stdout().flush().unwrap();
 
-                let mut choice = String::new();
-                io::stdin().read_line(&mut choice).unwrap();
+                let choice = read_line()?;
 
                 if let Ok(index) = choice.trim().parse::<usize>() {
                     if index >= 1 && index <= paths.len() {
-                        break paths[index - 1].clone();
+                        return Some(paths[index - 1].clone());
                     }
                 }
 
@@ -46,24 +269,48 @@ fn main() {
                 println!("❌ No matching file found. Please try again (inputs are case-sensitive and require the .osts extension.\n");
             }
         }
-    };
-
-    println!("\n📄 Found script at: {}\nBeginning Compilation...\n", path.display());
-
-    let content = read_file(&path.display().to_string());
-    let script = Osts::from_string(&content);
+    }
+}
 
-    let compiled = compile_from_string(&script.body).expect("❌ Compilation failed.");
+/// Reads a single line from stdin, returning `None` at end of input.
+#[cfg(feature = "interactive")]
+fn read_line() -> Option<String> {
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line),
+    }
+}
 
-    let mut clipboard = Clipboard::new().expect("❌ Clipboard not available.");
-    clipboard.set_text(compiled.clone()).expect("❌ Failed to copy to clipboard.");
+fn write_output(path: &Path, compiled: &str) -> ExitCode {
+    match fs::write(path, compiled) {
+        Ok(()) => {
+            eprintln!("✅ Frostbite compilation complete. Output written to {}", path.display());
+            ExitCode::from(exit_code::SUCCESS)
+        }
+        Err(e) => {
+            eprintln!("❌ Failed to write '{}': {}", path.display(), e);
+            ExitCode::from(exit_code::IO_ERROR)
+        }
+    }
+}
 
-    println!("✅ Frostbite compilation complete. Output copied to clipboard.");
+#[cfg(feature = "clipboard")]
+fn copy_to_clipboard(text: &str) -> Result<(), String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| format!("Clipboard not available: {}", e))?;
+    clipboard
+        .set_text(text.to_string())
+        .map_err(|e| format!("Failed to copy to clipboard: {}", e))
+}
 
-    pause_terminal();
+#[cfg(not(feature = "clipboard"))]
+fn copy_to_clipboard(_text: &str) -> Result<(), String> {
+    Err("Clipboard support is disabled in this build".to_string())
 }
 
+#[cfg(feature = "interactive")]
 fn pause_terminal() {
+    use std::io::Write;
     print!("Press Enter to exit...");
     io::stdout().flush().unwrap();
     let _ = io::stdin().read_line(&mut String::new());
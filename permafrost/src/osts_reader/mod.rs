@@ -1,9 +1,10 @@
+use std::fmt;
 use std::fs;
-use std::process;
+use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
+use crate::compile::error::CompileError;
 
-#[derive(Deserialize, Serialize, Debug)]
-#[allow(dead_code)] //REMOVE
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Osts {
     pub version: String,
@@ -14,56 +15,122 @@ pub struct Osts {
     pub api_info: String,
 }
 
-#[allow(dead_code)] //REMOVE
 impl Osts {
-    pub fn from_string(json_str: &str) -> Self {
-        match serde_json::from_str(json_str) {
-            Ok(parsed) => parsed,
-            Err(error) => {
-                eprintln!("Failed to parse JSON: {}", error);
-                std::process::exit(1);
+    pub fn from_string(json_str: &str) -> Result<Self, CompileError> {
+        serde_json::from_str(json_str).map_err(|error| {
+            // serde_json appends "at line X column Y", which is already reported separately
+            let message = error.to_string();
+            let message = message
+                .rsplit_once(" at line ")
+                .map_or(message.as_str(), |(msg, _)| msg)
+                .to_string();
+            CompileError::InvalidOsts {
+                file: None,
+                line: error.line(),
+                column: error.column(),
+                message,
             }
-        }
+        })
     }
 
+    /// Serializes the script back to `.osts` JSON.
+    #[deprecated(note = "`Osts` implements `Display`, format it with `{}` instead")]
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
-        match serde_json::to_string(&self) {
-            Ok(s) => s,
-            Err(error) => {
-                eprintln!("Failed to serialize JSON: {}", error);
-                std::process::exit(1);
-            }
-        }
+        format!("{}", self)
     }
 
-    pub fn write_to_file(&self, path: &str) {
-        let content = self.to_string();
-        if let Err(e) = fs::write(path, content) {
-            eprintln!("Failed to write to file '{}': {}", path, e);
-            std::process::exit(1);
+    /// Returns a copy of this script with its `body` replaced, keeping the version,
+    /// description, parameter/API info and no-code metadata untouched.
+    pub fn with_body(&self, body: String) -> Self {
+        Osts {
+            body,
+            ..self.clone()
         }
     }
+
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<(), CompileError> {
+        let path = path.as_ref();
+        fs::write(path, format!("{}", self)).map_err(|source| CompileError::Io {
+            file: path.to_path_buf(),
+            source,
+        })
+    }
 }
 
-pub fn read_file(file_path: &str) -> String {
-    match fs::read_to_string(file_path) {
-        Ok(content) => content,
-        Err(error) => {
-            eprintln!("Failed to read file '{}': {}", file_path, error);
-            process::exit(1);
-        }
+impl fmt::Display for Osts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
     }
 }
 
+pub fn read_file(file_path: impl AsRef<Path>) -> Result<String, CompileError> {
+    let file_path = file_path.as_ref();
+    fs::read_to_string(file_path).map_err(|source| CompileError::Io {
+        file: file_path.to_path_buf(),
+        source,
+    })
+}
+
+/// Builds the `<name>.min.osts` path that sits next to the given source script. A
+/// script that is already `<name>.min.osts` maps to itself rather than `.min.min.osts`.
+pub fn minified_path(source: &Path) -> PathBuf {
+    let stem = source
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("script");
+    let stem = stem.strip_suffix(".min").unwrap_or(stem);
+    source.with_file_name(format!("{}.min.osts", stem))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn read_write_roundtrip() {
-        let content = read_file("test_files/empty_test_file.osts");
-        let script = Osts::from_string(&content);
-        let output_content = script.to_string();
+        let content = read_file("test_files/empty_test_file.osts").unwrap();
+        let script = Osts::from_string(&content).unwrap();
+        let output_content = format!("{}", script);
         assert_eq!(content, output_content);
     }
+
+    #[test]
+    fn with_body_keeps_metadata() {
+        let content = read_file("test_files/test_export.osts").unwrap();
+        let script = Osts::from_string(&content).unwrap();
+        let minified = script.with_body("function main() {}".to_string());
+
+        assert_eq!(minified.body, "function main() {}");
+        assert_eq!(minified.version, script.version);
+        assert_eq!(minified.description, script.description);
+        assert_eq!(minified.parameter_info, script.parameter_info);
+        assert_eq!(minified.api_info, script.api_info);
+        assert_eq!(minified.no_code_metadata, script.no_code_metadata);
+    }
+
+    #[test]
+    fn invalid_json_reports_position() {
+        let err = Osts::from_string("{\n  \"version\": }").unwrap_err();
+        assert!(matches!(err, CompileError::InvalidOsts { line: 2, .. }));
+    }
+
+    #[test]
+    fn missing_file_is_io_error() {
+        let err = read_file("test_files/does_not_exist.osts").unwrap_err();
+        assert!(err.is_io());
+    }
+
+    #[test]
+    fn minified_path_sits_next_to_source() {
+        assert_eq!(
+            minified_path(Path::new("scripts/report.osts")),
+            PathBuf::from("scripts/report.min.osts")
+        );
+        assert_eq!(
+            minified_path(Path::new("scripts/report.min.osts")),
+            PathBuf::from("scripts/report.min.osts")
+        );
+    }
 }
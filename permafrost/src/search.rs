@@ -0,0 +1,46 @@
+//! # search
+//!
+//! Locates `.osts` scripts on disk for the interactive prompt. Searches the current
+//! directory along with the user's Documents and Downloads folders.
+//!
+//! Only available with the `search` feature.
+
+use walkdir::{WalkDir, DirEntry};
+use std::path::PathBuf;
+
+/// Finds every `.osts` file whose name ends with `targ`.
+pub fn find_files(targ: &str) -> Result<Vec<PathBuf>, String> {
+    let dirs_to_walk = vec![
+        Some(PathBuf::from(".")),
+        dirs::document_dir(),
+        dirs::download_dir(),
+    ];
+
+    let mut matches = vec![];
+
+    for maybe_dir in dirs_to_walk.into_iter().flatten() {
+        for entry in WalkDir::new(maybe_dir)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| is_osts(e) && e.file_name().to_str().unwrap_or("").ends_with(targ))
+        {
+            matches.push(entry.path().to_path_buf());
+        }
+    }
+
+    if matches.is_empty() {
+        Err(format!("Unable to find any file ending with `{}`", targ))
+    } else {
+        Ok(matches)
+    }
+}
+
+
+fn is_osts(entry: &DirEntry) -> bool {
+    entry.file_type().is_file() &&
+    entry.path()
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext_str| ext_str == "osts")
+        .unwrap_or(false)
+}